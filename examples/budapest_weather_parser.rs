@@ -6,19 +6,33 @@ extern crate debris;
 use debris::parse::Config;
 use debris::parse::Meta;
 use debris::parse::Parser;
+use debris::parse::PathDefinition;
 use debris::parse::Populator;
 use std::collections::HashMap;
 
 fn main() {
     let path = String::from(r#"START(SELECTOR: ul[id="results"]) -> FIND(NAME: daily, SELECTOR: a[class="title"], SELECT: ALL(-), LOC: ATTR(HREF))"#);
-    let paths = vec![path];
+    let paths = vec![PathDefinition::from(path)];
     let fields = HashMap::<String, String>::new();
     let meta = Meta {
         populator: String::from("single"),
         link_path: None,
         base_url: String::from("https://port.hu/programkereso/szinhaz?q=&interval=today&events_from=2019-05-13&events_until=2019-05-14&dft=i&cityMain=1&city=cityList-3372&area=theater&ageLimitFrom=2&ageLimitTo=10&s=start&onlyFav=0&documentId="),
+        local: None,
         paging: None,
         prepend_links: None,
+        max_failures: None,
+        max_failure_ratio: None,
+        retry: None,
+        rate_limit: None,
+        max_threads: None,
+        robots_user_agent: None,
+        ignore_robots_txt: false,
+        headers: None,
+        user_agent: None,
+        cookies: None,
+        accept_language: None,
+        session: None,
     };
     let config = Config {
         meta,
@@ -26,10 +40,10 @@ fn main() {
         fields,
     };
     let mut parser = Parser::new(config);
-    let populator = parser.build();
+    let populator = parser.build().expect("Invalid config");
     match populator {
         Populator::Single(mut p) => {
-            p.populate();
+            p.populate().expect("Population failed");
             for (k, v) in p.map {
                 println!("{} {}", k, v);
             }
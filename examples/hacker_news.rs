@@ -20,21 +20,18 @@ fn main() {
     let mut comment_fields = HashMap::new();
     comment_fields.insert(
         String::from("first"),
-        FieldIdentity {
-            destination: Destination::new("p", ElementSelection::All(String::from(" "))),
-            destination_location: DestinationLocation::Text,
-        },
+        FieldIdentity::new(
+            Destination::new("p", ElementSelection::All(String::from(" "))),
+            DestinationLocation::Text,
+        ),
     );
     let mut initial = HashMap::new();
     initial.insert(
         String::from("initial"),
-        FieldIdentity {
-            destination: Destination::new(
-                r#"span[class="commtext c00"]"#,
-                ElementSelection::first(),
-            ),
-            destination_location: DestinationLocation::Text,
-        },
+        FieldIdentity::new(
+            Destination::new(r#"span[class="commtext c00"]"#, ElementSelection::first()),
+            DestinationLocation::Text,
+        ),
     );
     let comment_path = PathBuilder::new()
         .start(Destination::new(
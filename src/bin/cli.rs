@@ -1,11 +1,17 @@
 extern crate debris;
 use clap::{App, Arg};
+use colored::*;
 use debris::parse::Config;
 use debris::parse::Meta;
 use debris::parse::Parser;
 use debris::parse::Populator;
+use debris::population::RecordingFetcher;
+use debris::population::ReplayFetcher;
+use debris::DebrisError;
 use std::fs::File;
 use std::io::Read;
+use std::process;
+use std::sync::Arc;
 
 fn main() {
     let matches = App::new("Debris CLI Application")
@@ -17,17 +23,72 @@ fn main() {
                 .help("Prints result to stdout")
                 .long("print"),
         )
+        .arg(
+            Arg::with_name("record")
+                .help("Stores every fetched response into the directory")
+                .long("record")
+                .value_name("DIR")
+                .conflicts_with("replay"),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .help("Serves the responses recorded into the directory instead of the network")
+                .long("replay")
+                .value_name("DIR"),
+        )
         .get_matches();
 
     let print = matches.is_present("print");
+    let source = match (matches.value_of("record"), matches.value_of("replay")) {
+        (Some(dir), _) => Source::Record(dir),
+        (_, Some(dir)) => Source::Replay(dir),
+        _ => Source::Network,
+    };
 
     match matches.value_of("path") {
-        Some(path) => process_file(path, print),
+        Some(path) => process_file(path, print, source),
         None => return,
     }
 }
 
-fn process_file(path: &str, print: bool) {
+/// Where the fetched pages come from.
+enum Source<'a> {
+    Network,
+    Record(&'a str),
+    Replay(&'a str),
+}
+
+fn exit_with_error(error: DebrisError) -> ! {
+    eprintln!("{}", highlight(&error.to_string()));
+    process::exit(1);
+}
+
+/// Colours the labels and the underlines of the parse errors rendered into the message.
+fn highlight(message: &str) -> String {
+    let lines: Vec<String> = message
+        .lines()
+        .map(|line| {
+            if line.starts_with("error: ") {
+                return format!("{}{}", "error".red().bold(), &line["error".len()..]);
+            }
+            match line.find(" | ") {
+                Some(bar) if is_underline(&line[..bar], &line[bar + 3..]) => {
+                    format!("{}{}", &line[..bar + 3], line[bar + 3..].red())
+                }
+                _ => String::from(line),
+            }
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// An underline has an empty gutter and only carets after the indentation.
+fn is_underline(gutter: &str, rest: &str) -> bool {
+    let carets = rest.trim_start();
+    gutter.trim().is_empty() && !carets.is_empty() && carets.chars().all(|c| c == '^')
+}
+
+fn process_file(path: &str, print: bool, source: Source) {
     let mut file = match File::open(path) {
         Ok(f) => f,
         Err(_) => panic!("Invalid path"),
@@ -40,10 +101,35 @@ fn process_file(path: &str, print: bool) {
         Err(error) => panic!(format!("{}", error)),
     };
     let mut parser = Parser::new(config);
-    let mut populator = parser.build();
-    populator.run();
+    match source {
+        Source::Record(dir) => {
+            let recorder = parser
+                .http_fetcher()
+                .and_then(|http| RecordingFetcher::new(Arc::new(http), dir));
+            match recorder {
+                Ok(recorder) => parser.set_fetcher(Arc::new(recorder)),
+                Err(error) => exit_with_error(error),
+            }
+        }
+        Source::Replay(dir) => parser.set_fetcher(Arc::new(ReplayFetcher::new(dir))),
+        Source::Network => (),
+    }
+    let mut populator = match parser.build() {
+        Ok(populator) => populator,
+        Err(error) => exit_with_error(error),
+    };
+    if let Err(error) = populator.run() {
+        // The cookies received before the failure are kept for the next run
+        if let Err(save_error) = parser.save_session() {
+            eprintln!("{}", save_error);
+        }
+        exit_with_error(error);
+    }
 
     if print {
         populator.print();
     }
+    if let Err(error) = parser.save_session() {
+        exit_with_error(error);
+    }
 }
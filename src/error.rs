@@ -0,0 +1,153 @@
+//! The error type shared by every part of the engine
+//!
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io;
+use std::result;
+
+pub type Result<T> = result::Result<T, DebrisError>;
+
+#[derive(Clone, Debug)]
+/// Every failure, that might occur while building or running a population.
+pub enum DebrisError {
+    /// The page could not be retrieved. The status is present, when the server responded, the io
+    /// failure, when the connection itself broke.
+    Fetch {
+        url: String,
+        status: Option<u16>,
+        io: Option<IoFailure>,
+        message: String,
+    },
+    /// A selector string could not be compiled.
+    Selector(String),
+    /// The path is malformed (eg. it does not begin with a start step).
+    Path(String),
+    /// The configuration of the population is invalid.
+    Config(String),
+    /// The searched value could not be extracted from the document.
+    Extraction(String),
+    /// A crawl exceeded its error budget and was stopped.
+    Aborted { failed: usize, attempted: usize },
+    /// The url is disallowed by the robots.txt of its host.
+    Disallowed(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The category of a [`DebrisError`] without its details.
+pub enum ErrorKind {
+    Fetch,
+    Selector,
+    Path,
+    Config,
+    Extraction,
+    Aborted,
+    Disallowed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// Classification of the connection failures, that prevented a response.
+pub enum IoFailure {
+    /// The request did not complete in time.
+    Timeout,
+    /// The connection could not be established.
+    Connect,
+    /// The connection was closed by the peer during the exchange.
+    Reset,
+    Other,
+}
+
+impl IoFailure {
+    pub fn from_io_kind(kind: io::ErrorKind) -> IoFailure {
+        match kind {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => IoFailure::Timeout,
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::AddrNotAvailable => IoFailure::Connect,
+            io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof => IoFailure::Reset,
+            _ => IoFailure::Other,
+        }
+    }
+}
+
+impl DebrisError {
+    pub fn fetch(url: &str, status: Option<u16>, message: &str) -> DebrisError {
+        DebrisError::Fetch {
+            url: String::from(url),
+            status,
+            io: None,
+            message: String::from(message),
+        }
+    }
+
+    pub fn io(url: &str, failure: IoFailure, message: &str) -> DebrisError {
+        DebrisError::Fetch {
+            url: String::from(url),
+            status: None,
+            io: Some(failure),
+            message: String::from(message),
+        }
+    }
+
+    pub fn invalid_selector(selector: &str) -> DebrisError {
+        DebrisError::Selector(format!("Invalid selector: {}", selector))
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            DebrisError::Fetch { .. } => ErrorKind::Fetch,
+            DebrisError::Selector(_) => ErrorKind::Selector,
+            DebrisError::Path(_) => ErrorKind::Path,
+            DebrisError::Config(_) => ErrorKind::Config,
+            DebrisError::Extraction(_) => ErrorKind::Extraction,
+            DebrisError::Aborted { .. } => ErrorKind::Aborted,
+            DebrisError::Disallowed(_) => ErrorKind::Disallowed,
+        }
+    }
+
+    /// The HTTP status of the response, that caused the error.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            DebrisError::Fetch { status, .. } => *status,
+            _ => None,
+        }
+    }
+}
+
+impl Error for DebrisError {}
+
+impl Display for DebrisError {
+    fn fmt(&self, f: &mut Formatter) -> result::Result<(), fmt::Error> {
+        match self {
+            DebrisError::Fetch {
+                url,
+                status: Some(status),
+                message,
+                ..
+            } => write!(
+                f,
+                "Fetching {} failed with status {}: {}",
+                url, status, message
+            ),
+            DebrisError::Fetch { url, message, .. } => {
+                write!(f, "Fetching {} failed: {}", url, message)
+            }
+            DebrisError::Selector(message) => write!(f, "{}", message),
+            DebrisError::Path(message) => write!(f, "Invalid path: {}", message),
+            DebrisError::Config(message) => write!(f, "Invalid config: {}", message),
+            DebrisError::Extraction(message) => write!(f, "Extraction failed: {}", message),
+            DebrisError::Aborted { failed, attempted } => write!(
+                f,
+                "Crawl aborted after {} failed links out of {}",
+                failed, attempted
+            ),
+            DebrisError::Disallowed(url) => write!(f, "{} is disallowed by robots.txt", url),
+        }
+    }
+}
@@ -0,0 +1,463 @@
+//! Sources of HTML documents used by the populators
+//!
+use crate::error::DebrisError;
+use crate::error::IoFailure;
+use crate::error::Result;
+use crate::session::CookieJar;
+use glob::glob;
+use reqwest;
+use reqwest::header::HeaderMap;
+use reqwest::header::HeaderName;
+use reqwest::header::HeaderValue;
+use reqwest::header::ACCEPT_LANGUAGE;
+use reqwest::header::COOKIE;
+use reqwest::header::LOCATION;
+use reqwest::header::SET_COOKIE;
+use reqwest::header::USER_AGENT;
+use reqwest::Client;
+use reqwest::RedirectPolicy;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const FILE_SCHEME: &str = "file://";
+const HTML_EXTENSIONS: [&str; 2] = ["html", "htm"];
+const MAX_REDIRECTS: usize = 10;
+
+/// Retrieves the HTML document behind an url. Every populator receives its pages through a fetcher,
+/// so the network can be replaced (eg. in tests) without touching the search parameters.
+///
+/// A response with an unsuccessful status is still returned as `Ok`, the populators decide what to
+/// do with it. Errors are reserved for documents, that could not be retrieved at all.
+pub trait Fetcher: Send + Sync {
+    fn fetch(&self, url: &str) -> Result<Response>;
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A fetched document with the details of the response it arrived in.
+pub struct Response {
+    pub url: String,
+    pub status: u16,
+    /// Response headers in the order they were received. A header might occur multiple times.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    /// A successful response without any headers.
+    pub fn new(url: &str, body: &str) -> Response {
+        Response {
+            url: String::from(url),
+            status: 200,
+            headers: Vec::new(),
+            body: String::from(body),
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+
+    /// Returns the first value of a header. Header names are matched case insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Headers sent with every request of an [`HttpFetcher`].
+pub struct RequestSettings {
+    pub headers: Vec<(String, String)>,
+    pub user_agent: Option<String>,
+    /// Cookies as name and value pairs, sent in a single `Cookie` header.
+    pub cookies: Vec<(String, String)>,
+    pub accept_language: Option<String>,
+}
+
+impl RequestSettings {
+    pub fn new() -> RequestSettings {
+        RequestSettings::default()
+    }
+
+    pub fn header(&mut self, name: &str, value: &str) -> &mut Self {
+        self.headers.push((String::from(name), String::from(value)));
+        return self;
+    }
+
+    pub fn user_agent(&mut self, user_agent: &str) -> &mut Self {
+        self.user_agent = Some(String::from(user_agent));
+        return self;
+    }
+
+    pub fn cookie(&mut self, name: &str, value: &str) -> &mut Self {
+        self.cookies.push((String::from(name), String::from(value)));
+        return self;
+    }
+
+    pub fn accept_language(&mut self, language: &str) -> &mut Self {
+        self.accept_language = Some(String::from(language));
+        return self;
+    }
+
+    /// Returns the constructed settings
+    pub fn build(&self) -> RequestSettings {
+        self.clone()
+    }
+
+    /// Converts the settings into headers. The dedicated settings override the same header given
+    /// in `headers`.
+    pub fn header_map(&self) -> Result<HeaderMap> {
+        let mut map = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| DebrisError::Config(format!("Invalid header name: {}", name)))?;
+            map.append(name, header_value(value)?);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            map.insert(USER_AGENT, header_value(user_agent)?);
+        }
+        if let Some(language) = &self.accept_language {
+            map.insert(ACCEPT_LANGUAGE, header_value(language)?);
+        }
+        if let Some(cookies) = self.cookie_header() {
+            map.insert(COOKIE, header_value(&cookies)?);
+        }
+
+        Ok(map)
+    }
+
+    fn cookie_header(&self) -> Option<String> {
+        if self.cookies.is_empty() {
+            return None;
+        }
+        let cookies: Vec<String> = self
+            .cookies
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        Some(cookies.join("; "))
+    }
+}
+
+fn header_value(value: &str) -> Result<HeaderValue> {
+    HeaderValue::from_str(value)
+        .map_err(|_| DebrisError::Config(format!("Invalid header value: {}", value)))
+}
+
+#[derive(Clone)]
+/// The default fetcher, which downloads the page with [`reqwest`].
+pub struct HttpFetcher {
+    client: Client,
+    /// Follows the redirects itself, when a jar is used, to store the cookies of every hop.
+    jar: Option<Arc<CookieJar>>,
+    /// The cookies of the settings, sent along with the cookies of the jar.
+    cookies: Option<String>,
+}
+
+impl HttpFetcher {
+    pub fn new() -> HttpFetcher {
+        HttpFetcher {
+            client: Client::new(),
+            jar: None,
+            cookies: None,
+        }
+    }
+
+    /// A fetcher sending the headers of the settings with every request.
+    pub fn with_settings(settings: &RequestSettings) -> Result<HttpFetcher> {
+        let client = Client::builder()
+            .default_headers(settings.header_map()?)
+            .build()
+            .map_err(|e| DebrisError::Config(e.to_string()))?;
+        Ok(HttpFetcher {
+            client,
+            jar: None,
+            cookies: None,
+        })
+    }
+
+    /// A fetcher, that stores the received cookies into the jar and sends them back.
+    pub fn with_session(settings: &RequestSettings, jar: Arc<CookieJar>) -> Result<HttpFetcher> {
+        let client = Client::builder()
+            .default_headers(settings.header_map()?)
+            .redirect(RedirectPolicy::none())
+            .build()
+            .map_err(|e| DebrisError::Config(e.to_string()))?;
+        Ok(HttpFetcher {
+            client,
+            jar: Some(jar),
+            cookies: settings.cookie_header(),
+        })
+    }
+
+    /// Posts the fields url-encoded.
+    pub fn submit_form(&self, url: &str, fields: &[(String, String)]) -> Result<Response> {
+        self.request(url, Some(fields))
+    }
+
+    fn request(&self, url: &str, form: Option<&[(String, String)]>) -> Result<Response> {
+        let jar = match &self.jar {
+            Some(jar) => jar,
+            None => return self.send(url, form),
+        };
+
+        let mut target = String::from(url);
+        let mut form = form;
+        for _ in 0..=MAX_REDIRECTS {
+            let response = self.send(&target, form)?;
+            for (name, value) in &response.headers {
+                if name.eq_ignore_ascii_case(SET_COOKIE.as_str()) {
+                    jar.store(&target, value);
+                }
+            }
+            match redirect_target(&target, &response) {
+                Some(next) => {
+                    if response.status != 307 && response.status != 308 {
+                        form = None;
+                    }
+                    target = next;
+                }
+                None => {
+                    return Ok(Response {
+                        url: String::from(url),
+                        ..response
+                    })
+                }
+            }
+        }
+        Err(DebrisError::fetch(url, None, "Too many redirects"))
+    }
+
+    fn send(&self, url: &str, form: Option<&[(String, String)]>) -> Result<Response> {
+        let mut request = match form {
+            Some(fields) => self.client.post(url).form(fields),
+            None => self.client.get(url),
+        };
+        if let Some(jar_cookies) = self.jar.as_ref().and_then(|jar| jar.cookie_header(url)) {
+            let cookies = match &self.cookies {
+                Some(cookies) => format!("{}; {}", cookies, jar_cookies),
+                None => jar_cookies,
+            };
+            request = request.header(COOKIE, header_value(&cookies)?);
+        }
+
+        let mut response = request.send().map_err(|e| request_error(url, &e))?;
+        let headers = response
+            .headers()
+            .iter()
+            .map(|(k, v)| (k.as_str().to_string(), v.to_str().unwrap_or("").to_string()))
+            .collect();
+        let status = response.status().as_u16();
+        let body = response
+            .text()
+            .map_err(|e| DebrisError::fetch(url, Some(status), &e.to_string()))?;
+        Ok(Response {
+            url: String::from(url),
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+impl Fetcher for HttpFetcher {
+    fn fetch(&self, url: &str) -> Result<Response> {
+        self.request(url, None)
+    }
+}
+
+/// The absolute url a redirect response points to.
+fn redirect_target(url: &str, response: &Response) -> Option<String> {
+    match response.status {
+        301 | 302 | 303 | 307 | 308 => {
+            let location = response.header(LOCATION.as_str())?;
+            let target = Url::parse(url).ok()?.join(location).ok()?;
+            Some(target.into_string())
+        }
+        _ => None,
+    }
+}
+
+/// Converts a failed request, classifying the connection failures.
+fn request_error(url: &str, error: &reqwest::Error) -> DebrisError {
+    if let Some(status) = error.status() {
+        return DebrisError::fetch(url, Some(status.as_u16()), &error.to_string());
+    }
+    if error.is_timeout() {
+        return DebrisError::io(url, IoFailure::Timeout, &error.to_string());
+    }
+
+    let mut source = error.source();
+    while let Some(cause) = source {
+        if let Some(io_error) = cause.downcast_ref::<io::Error>() {
+            let failure = IoFailure::from_io_kind(io_error.kind());
+            return DebrisError::io(url, failure, &error.to_string());
+        }
+        source = cause.source();
+    }
+    DebrisError::io(url, IoFailure::Other, &error.to_string())
+}
+
+/// An in-memory fetcher, that serves pages from a map keyed by url. Unknown urls are answered with
+/// a `404` response.
+#[derive(Clone)]
+pub struct MapFetcher {
+    pages: HashMap<String, String>,
+}
+
+impl MapFetcher {
+    pub fn new() -> MapFetcher {
+        MapFetcher {
+            pages: HashMap::new(),
+        }
+    }
+
+    /// Register the HTML content to be served for the url.
+    pub fn insert_page(&mut self, url: &str, html: &str) {
+        self.pages.insert(String::from(url), String::from(html));
+    }
+}
+
+impl Fetcher for MapFetcher {
+    fn fetch(&self, url: &str) -> Result<Response> {
+        match self.pages.get(url) {
+            Some(page) => Ok(Response::new(url, page)),
+            None => Ok(Response {
+                status: 404,
+                ..Response::new(url, "")
+            }),
+        }
+    }
+}
+
+/// A fetcher reading saved pages from the file system. The url is either a plain path or a
+/// `file://` url.
+pub struct FileFetcher;
+
+impl FileFetcher {
+    pub fn new() -> FileFetcher {
+        FileFetcher
+    }
+}
+
+impl Fetcher for FileFetcher {
+    fn fetch(&self, url: &str) -> Result<Response> {
+        let path = url.trim_start_matches(FILE_SCHEME);
+        match fs::read_to_string(path) {
+            Ok(page) => Ok(Response::new(url, &page)),
+            Err(e) => Err(DebrisError::fetch(url, None, &e.to_string())),
+        }
+    }
+}
+
+/// Collects the HTML files behind a local source, which might be a single file, a directory
+/// (every `.html` and `.htm` file directly inside it) or a glob pattern. The files are returned in
+/// lexical order.
+pub fn local_pages(source: &str) -> Vec<PathBuf> {
+    let source = source.trim_start_matches(FILE_SCHEME);
+    let path = Path::new(source);
+    let mut pages: Vec<PathBuf> = if path.is_file() {
+        vec![path.to_path_buf()]
+    } else if path.is_dir() {
+        match fs::read_dir(path) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.is_file() && is_html(p))
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    } else {
+        match glob(source) {
+            Ok(paths) => paths
+                .filter_map(|p| p.ok())
+                .filter(|p| p.is_file())
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    };
+    pages.sort();
+
+    pages
+}
+
+fn is_html(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => HTML_EXTENSIONS.contains(&extension.to_lowercase().as_str()),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn fixture_dir(name: &str) -> TempDir {
+        let dir = TempDir::new(name);
+        fs::write(dir.join("b.html"), "<p>second</p>").unwrap();
+        fs::write(dir.join("a.htm"), "<p>first</p>").unwrap();
+        fs::write(dir.join("notes.txt"), "not a page").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_local_pages_of_directory() {
+        let dir = fixture_dir("directory");
+
+        let pages = local_pages(dir.path().to_str().unwrap());
+
+        assert_eq!(vec![dir.join("a.htm"), dir.join("b.html")], pages);
+    }
+
+    #[test]
+    fn test_local_pages_of_glob() {
+        let dir = fixture_dir("glob");
+
+        let pages = local_pages(&format!("{}/*.html", dir.path().to_str().unwrap()));
+
+        assert_eq!(vec![dir.join("b.html")], pages);
+    }
+
+    #[test]
+    fn test_request_settings_headers() {
+        let settings = RequestSettings::new()
+            .header("X-Requested-With", "debris")
+            .header("User-Agent", "overridden")
+            .user_agent("Mozilla/5.0")
+            .cookie("session", "abc")
+            .cookie("lang", "hu")
+            .accept_language("hu-HU")
+            .build();
+
+        let headers = settings.header_map().unwrap();
+
+        assert_eq!("debris", headers["x-requested-with"]);
+        assert_eq!("Mozilla/5.0", headers[USER_AGENT]);
+        assert_eq!("session=abc; lang=hu", headers[COOKIE]);
+        assert_eq!("hu-HU", headers[ACCEPT_LANGUAGE]);
+    }
+
+    #[test]
+    fn test_error_on_invalid_header() {
+        let settings = RequestSettings::new().header("Bad Header", "value").build();
+
+        matches::assert_matches!(settings.header_map(), Err(DebrisError::Config(_)));
+    }
+
+    #[test]
+    fn test_file_fetcher_reads_file_url() {
+        let dir = fixture_dir("file");
+        let url = format!("file://{}", dir.join("a.htm").to_str().unwrap());
+
+        assert_eq!("<p>first</p>", FileFetcher::new().fetch(&url).unwrap().body);
+    }
+}
@@ -1,53 +1,358 @@
+use crate::error::DebrisError;
+use crate::error::Result;
+use crate::label;
+use crate::transform;
+use crate::transform::Transform;
+use crate::xpath;
+use regex::Regex;
+use regex::RegexBuilder;
 use scraper::ElementRef;
 use scraper::Html;
-use scraper::Selector;
+use scraper::Selector as CssSelector;
+use serde::{Deserialize, Serialize};
 use std::cell::Ref;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+thread_local! {
+    /// The regular expressions of the text filters by their pattern and case sensitivity.
+    static COMPILED_FILTERS: RefCell<HashMap<(String, bool), Regex>> = RefCell::new(HashMap::new());
+}
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 /// Contains information about the HTML element to be extracted in the process.
 pub struct FieldIdentity {
     pub destination: Destination,
     pub destination_location: DestinationLocation,
+    /// Applied in order on every extracted value.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transforms: Vec<Transform>,
+}
+
+impl FieldIdentity {
+    /// The identity of the value at the location of the destination, without transforms.
+    pub fn new(
+        destination: Destination,
+        destination_location: DestinationLocation,
+    ) -> FieldIdentity {
+        FieldIdentity {
+            destination,
+            destination_location,
+            transforms: Vec::new(),
+        }
+    }
+
+    /// The identity with the transform applied after the ones so far.
+    pub fn with_transform(mut self, transform: Transform) -> FieldIdentity {
+        self.transforms.push(transform);
+        self
+    }
 }
 
-#[derive(Clone, Debug)]
-/// A simple tuple, with a selector string and a selection number.
-pub struct Destination(pub String, pub ElementSelection);
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "DestinationForm", into = "DestinationForm")]
+/// A simple tuple, with a selector, a selection number and an optional filter on the text of the
+/// matched elements.
+pub struct Destination(pub Selector, pub ElementSelection, pub Option<TextFilter>);
 
 impl<'a> Destination {
+    /// A destination selected by a CSS selector.
     pub fn new(selector: &'a str, selection: ElementSelection) -> Destination {
-        Destination(String::from(selector), selection)
+        Destination(Selector::from(selector), selection, None)
+    }
+
+    /// A destination selected by an XPath expression.
+    pub fn xpath(expression: &'a str, selection: ElementSelection) -> Destination {
+        Destination(Selector::XPath(String::from(expression)), selection, None)
+    }
+
+    /// The destination keeping only the matched elements with the text.
+    pub fn with_text(self, filter: TextFilter) -> Destination {
+        Destination(self.0, self.1, Some(filter))
+    }
+
+    /// The selected elements below the element.
+    pub fn select<'b>(&self, element: &ElementRef<'b>) -> Result<Vec<ElementRef<'b>>> {
+        let matches = self.0.select(element)?;
+        self.narrow(matches)
+    }
+
+    /// Filters the matched elements by their text, then picks the selected ones.
+    pub fn narrow<'b>(&self, matches: Vec<ElementRef<'b>>) -> Result<Vec<ElementRef<'b>>> {
+        let matches = match &self.2 {
+            Some(filter) => {
+                let regex = filter.compile()?;
+                matches
+                    .into_iter()
+                    .filter(|element| regex.is_match(&normalized_text(element)))
+                    .collect()
+            }
+            None => matches,
+        };
+        Ok(self.1.pick(matches))
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+/// A destination without a text filter is serialized as `[selector, selection]`.
+enum DestinationForm {
+    Filtered(Selector, ElementSelection, TextFilter),
+    Plain(Selector, ElementSelection),
+}
+
+impl From<DestinationForm> for Destination {
+    fn from(form: DestinationForm) -> Destination {
+        match form {
+            DestinationForm::Filtered(selector, selection, filter) => {
+                Destination(selector, selection, Some(filter))
+            }
+            DestinationForm::Plain(selector, selection) => Destination(selector, selection, None),
+        }
+    }
+}
+
+impl From<Destination> for DestinationForm {
+    fn from(destination: Destination) -> DestinationForm {
+        match destination {
+            Destination(selector, selection, Some(filter)) => {
+                DestinationForm::Filtered(selector, selection, filter)
+            }
+            Destination(selector, selection, None) => DestinationForm::Plain(selector, selection),
+        }
     }
 }
 
-#[derive(Clone, Debug)]
-/// Provides a way to select an exact HTML element, or all the elements.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// Keeps the elements, whose text matches. The text of an element is trimmed and its whitespaces
+/// are collapsed before the comparison.
+pub struct TextFilter {
+    pub mode: TextMatch,
+    pub text: String,
+    #[serde(default)]
+    pub ignore_case: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextMatch {
+    /// The text equals the filter text.
+    Exact,
+    /// The text contains the filter text.
+    Contains,
+    /// The text matches the filter text as a regular expression.
+    Regex,
+}
+
+impl TextFilter {
+    pub fn new(mode: TextMatch, text: &str) -> TextFilter {
+        TextFilter {
+            mode,
+            text: String::from(text),
+            ignore_case: false,
+        }
+    }
+
+    /// Compiles the filter into a regular expression searched in the text. The expression is
+    /// compiled once per thread.
+    pub fn compile(&self) -> Result<Regex> {
+        let pattern = match self.mode {
+            TextMatch::Exact => format!("^{}$", regex::escape(&self.text)),
+            TextMatch::Contains => regex::escape(&self.text),
+            TextMatch::Regex => self.text.clone(),
+        };
+        let key = (pattern, self.ignore_case);
+        let cached = COMPILED_FILTERS.with(|compiled| compiled.borrow().get(&key).cloned());
+        if let Some(regex) = cached {
+            return Ok(regex);
+        }
+        let regex = RegexBuilder::new(&key.0)
+            .case_insensitive(self.ignore_case)
+            .build()
+            .map_err(|_| DebrisError::Selector(format!("Invalid text pattern: {}", self.text)))?;
+        COMPILED_FILTERS.with(|compiled| compiled.borrow_mut().insert(key, regex.clone()));
+        Ok(regex)
+    }
+}
+
+/// The text of the element, trimmed and with its runs of whitespaces collapsed into a space.
+pub(crate) fn normalized_text(element: &ElementRef) -> String {
+    element
+        .text()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "SelectorForm", into = "SelectorForm")]
+/// Selects elements with a CSS selector or an XPath 1.0 expression.
+pub enum Selector {
+    Css(String),
+    XPath(String),
+}
+
+impl Selector {
+    /// The elements matching the selector below the element in document order. An XPath is
+    /// evaluated in the context of the element.
+    pub fn select<'b>(&self, element: &ElementRef<'b>) -> Result<Vec<ElementRef<'b>>> {
+        match self {
+            Selector::Css(css) => {
+                let selector = parse_selector(css)?;
+                Ok(element.select(&selector).collect())
+            }
+            Selector::XPath(expression) => xpath::select(element, expression),
+        }
+    }
+
+    /// The first of the candidates matching the selector. An XPath matches a candidate, when it
+    /// selects the candidate in the context of one of its ancestors (eg. `td` matches a `td`).
+    pub fn first_match<'b, I>(&self, mut candidates: I) -> Result<Option<ElementRef<'b>>>
+    where
+        I: Iterator<Item = ElementRef<'b>>,
+    {
+        match self {
+            Selector::Css(css) => {
+                let selector = parse_selector(css)?;
+                Ok(candidates.find(|candidate| selector.matches(candidate)))
+            }
+            Selector::XPath(expression) => xpath::first_match(expression, candidates),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Selector {
+    fn from(css: &'a str) -> Selector {
+        Selector::Css(String::from(css))
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+/// A CSS selector is serialized as a string, an XPath as `{"xpath": "..."}`.
+enum SelectorForm {
+    Css(String),
+    XPath { xpath: String },
+}
+
+impl From<SelectorForm> for Selector {
+    fn from(form: SelectorForm) -> Selector {
+        match form {
+            SelectorForm::Css(css) => Selector::Css(css),
+            SelectorForm::XPath { xpath } => Selector::XPath(xpath),
+        }
+    }
+}
+
+impl From<Selector> for SelectorForm {
+    fn from(selector: Selector) -> SelectorForm {
+        match selector {
+            Selector::Css(css) => SelectorForm::Css(css),
+            Selector::XPath(xpath) => SelectorForm::XPath { xpath },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// Provides a way to select an exact HTML element, or several of them. Negative indexes count from
+/// the end (eg. `-1` is the last element). The values of several elements are joined with the
+/// delimiter.
 pub enum ElementSelection {
     /// Select the nth element.
     Single(i32),
+    /// Select the last element.
+    Last,
     /// Select all the elements.
     /// > Might not be usable on every occasion! (eg. on [`PathStep::Descend`])
     All(String),
+    /// Select the elements from the start index up to the end index (exclusive), or up to the last
+    /// element without an end.
+    Range(i32, Option<i32>, String),
+    /// Select every nth element starting from the offset.
+    Every(usize, usize, String),
 }
 
 impl ElementSelection {
     pub fn first() -> ElementSelection {
         ElementSelection::Single(0)
     }
+
+    /// The delimiter joining the values, when several elements might be selected.
+    pub fn delimiter(&self) -> Option<&str> {
+        match self {
+            ElementSelection::Single(_) | ElementSelection::Last => None,
+            ElementSelection::All(delimiter)
+            | ElementSelection::Range(_, _, delimiter)
+            | ElementSelection::Every(_, _, delimiter) => Some(delimiter),
+        }
+    }
+
+    pub fn is_multiple(&self) -> bool {
+        self.delimiter().is_some()
+    }
+
+    /// Picks the selected elements out of the matched ones, keeping their order.
+    pub fn pick<T>(&self, matches: Vec<T>) -> Vec<T> {
+        let length = matches.len() as i64;
+        matches
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| self.is_picked(*i as i64, length))
+            .map(|(_, matched)| matched)
+            .collect()
+    }
+
+    fn is_picked(&self, i: i64, length: i64) -> bool {
+        let index = |n: i32| match n < 0 {
+            true => length + n as i64,
+            false => n as i64,
+        };
+        match self {
+            ElementSelection::Single(n) => i == index(*n),
+            ElementSelection::Last => i == length - 1,
+            ElementSelection::All(_) => true,
+            ElementSelection::Range(start, end, _) => {
+                index(*start) <= i && end.map_or(true, |end| i < index(end))
+            }
+            ElementSelection::Every(step, offset, _) => {
+                let offset = *offset as i64;
+                i >= offset && (i - offset) % cmp::max(*step, 1) as i64 == 0
+            }
+        }
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 /// Selects what to extract from the HTML element.
 pub enum DestinationLocation {
     /// Extract the element's attribute. (eg. to extract date from `<div itemprop=date></div>`, the
     /// argument is itemprop).
     Attr(String),
     /// Extract the element's id.
-    Id(String),
-    /// Extract the element's class.
-    Class(String),
+    Id,
+    /// Extract the element's classes separated by spaces.
+    Class,
     /// Extract text. (eg. to extract HELLO from `<div>HELLO</div>`)
     Text,
+    /// Extract the text of the element without its descendants. (eg. to extract HELLO from
+    /// `<div>HELLO <b>WORLD</b></div>`)
+    OwnText,
+    /// Extract the HTML content of the element.
+    InnerHtml,
+    /// Extract the HTML of the element including its own tag.
+    OuterHtml,
+    /// Extract the element's tag name. (eg. `div`)
+    TagName,
+    /// Extract every attribute of the element as a JSON object.
+    AllAttributes,
+    /// Extract the value of a label: the text of the nearest following sibling matching the
+    /// selector (eg. the `dd` after a `dt`), or the text following the label without a selector.
+    Following(Option<Selector>),
 }
 
 pub struct FieldPopulator<'a, 'b> {
@@ -65,82 +370,72 @@ impl<'a, 'b> FieldPopulator<'a, 'b> {
         }
     }
 
-    pub fn find_field(&mut self) {
-        match &self.identifier.destination.1 {
-            ElementSelection::Single(n) => {
-                self.value = Some(find_single(
-                    &self.html.root_element(),
-                    self.identifier,
-                    *n as usize,
-                ));
-            }
-            ElementSelection::All(delimiter) => {
-                self.value = Some(concatenate_all(
-                    &self.html.root_element(),
-                    self.identifier,
-                    delimiter,
-                ));
-            }
-        }
+    pub fn find_field(&mut self) -> Result<()> {
+        self.value = Some(find_value(&self.html.root_element(), self.identifier)?);
+        Ok(())
     }
 }
 
-pub fn concatenate_all(
-    element: &ElementRef,
-    identifier: &FieldIdentity,
-    delimiter: &str,
-) -> String {
-    let selector = Selector::parse(&identifier.destination.0).unwrap();
-    let selection = element.select(&selector);
-
-    let mut value = String::new();
-    for selected_element in selection {
-        value +=
-            &(extract(&selected_element, &identifier.destination_location) + &delimiter.clone());
-    }
-
-    String::from(value.trim())
+/// Compiles a CSS selector string.
+pub fn parse_selector(selector: &str) -> Result<CssSelector> {
+    CssSelector::parse(selector).map_err(|_| DebrisError::invalid_selector(selector))
 }
 
-pub fn find_all(element: &ElementRef, identifier: &FieldIdentity) -> Vec<String> {
-    let selector = Selector::parse(&identifier.destination.0).unwrap();
-    let selection = element.select(&selector);
-    let mut values = Vec::new();
-
-    for child_element in selection {
-        values.push(extract(&child_element, &identifier.destination_location));
-    }
+/// Values of the elements selected by the identifier below the element.
+pub fn find_selected(element: &ElementRef, identifier: &FieldIdentity) -> Result<Vec<String>> {
+    let selection = identifier.destination.select(element)?;
 
-    values
+    let values = selection
+        .iter()
+        .map(|selected| extract(selected, &identifier.destination_location))
+        .collect::<Result<_>>()?;
+    transform::apply(&identifier.transforms, values)
 }
 
-pub fn find_single(
-    element: &ElementRef,
-    identifier: &FieldIdentity,
-    selection_number: usize,
-) -> String {
-    let selector = Selector::parse(&identifier.destination.0).unwrap();
-    let mut selection = element.select(&selector);
-
-    match selection.nth(selection_number) {
-        Some(e) => extract(&e, &identifier.destination_location),
-        None => String::from(""),
+/// The value of a field: the single selected value (empty, when it is missing) or the joined values
+/// of several elements.
+pub fn find_value(element: &ElementRef, identifier: &FieldIdentity) -> Result<String> {
+    let values = find_selected(element, identifier)?;
+    match identifier.destination.1.delimiter() {
+        Some(delimiter) => Ok(String::from(values.join(delimiter).trim())),
+        None => Ok(values.into_iter().next().unwrap_or_default()),
     }
 }
 
-pub fn extract(element: &ElementRef, location: &DestinationLocation) -> String {
-    match location {
+pub fn extract(element: &ElementRef, location: &DestinationLocation) -> Result<String> {
+    Ok(match location {
         DestinationLocation::Text => element.text().collect::<Vec<_>>().join(" "),
         DestinationLocation::Attr(attr) => element.value().attr(&attr).unwrap_or("").to_string(),
-        _ => "".to_string(),
-    }
+        DestinationLocation::Id => element.value().id().unwrap_or("").to_string(),
+        // The classes in the order of the document
+        DestinationLocation::Class => element
+            .value()
+            .attr("class")
+            .map_or(Vec::new(), |class| class.split_whitespace().collect())
+            .join(" "),
+        DestinationLocation::OwnText => element
+            .children()
+            .filter_map(|child| child.value().as_text())
+            .map(|text| &**text)
+            .collect::<String>(),
+        DestinationLocation::InnerHtml => element.inner_html(),
+        DestinationLocation::OuterHtml => element.html(),
+        DestinationLocation::TagName => element.value().name().to_string(),
+        DestinationLocation::AllAttributes => {
+            let attributes: BTreeMap<&str, &str> = element.value().attrs().collect();
+            serde_json::to_string(&attributes).unwrap_or_default()
+        }
+        DestinationLocation::Following(value) => label::following(element, value.as_ref())?,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::{
-        Destination, DestinationLocation, ElementSelection, FieldIdentity, FieldPopulator,
+        extract, parse_selector, DebrisError, Destination, DestinationLocation, ElementSelection,
+        FieldIdentity, FieldPopulator, Selector, TextFilter, TextMatch,
     };
+    use crate::transform::{Normalization, Transform};
     use scraper::Html;
     use std::cell::RefCell;
 
@@ -149,13 +444,13 @@ mod tests {
         let html_string = r#"<div>find me</div>"#;
         let html = Html::parse_fragment(&html_string);
         let html = RefCell::new(&html);
-        let identity = FieldIdentity {
-            destination: Destination::new("div", ElementSelection::first()),
-            destination_location: DestinationLocation::Text,
-        };
+        let identity = FieldIdentity::new(
+            Destination::new("div", ElementSelection::first()),
+            DestinationLocation::Text,
+        );
         let mut field_populator = FieldPopulator::new(html.borrow(), &identity);
 
-        field_populator.find_field();
+        field_populator.find_field().unwrap();
 
         assert_eq!(field_populator.value.unwrap(), "find me");
     }
@@ -165,13 +460,13 @@ mod tests {
         let html_string = r#"<div><p>find me<p></div>"#;
         let html = Html::parse_fragment(&html_string);
         let html = RefCell::new(&html);
-        let identity = FieldIdentity {
-            destination: Destination::new("p", ElementSelection::first()),
-            destination_location: DestinationLocation::Text,
-        };
+        let identity = FieldIdentity::new(
+            Destination::new("p", ElementSelection::first()),
+            DestinationLocation::Text,
+        );
         let mut field_populator = FieldPopulator::new(html.borrow(), &identity);
 
-        field_populator.find_field();
+        field_populator.find_field().unwrap();
 
         assert_eq!(field_populator.value.unwrap(), "find me");
     }
@@ -181,13 +476,13 @@ mod tests {
         let html_string = r#"<div>find me</div> <div>as well</div>"#;
         let html = Html::parse_fragment(&html_string);
         let html = RefCell::new(&html);
-        let identity = FieldIdentity {
-            destination: Destination::new("div", ElementSelection::All(String::from(" "))),
-            destination_location: DestinationLocation::Text,
-        };
+        let identity = FieldIdentity::new(
+            Destination::new("div", ElementSelection::All(String::from(" "))),
+            DestinationLocation::Text,
+        );
         let mut field_populator = FieldPopulator::new(html.borrow(), &identity);
 
-        field_populator.find_field();
+        field_populator.find_field().unwrap();
 
         assert_eq!(field_populator.value.unwrap(), "find me as well");
     }
@@ -197,13 +492,13 @@ mod tests {
         let html_string = r#"<p><div>find me</div> <div>as well</div></p>"#;
         let html = Html::parse_fragment(&html_string);
         let html = RefCell::new(&html);
-        let identity = FieldIdentity {
-            destination: Destination::new("div", ElementSelection::All(String::from(" "))),
-            destination_location: DestinationLocation::Text,
-        };
+        let identity = FieldIdentity::new(
+            Destination::new("div", ElementSelection::All(String::from(" "))),
+            DestinationLocation::Text,
+        );
         let mut field_populator = FieldPopulator::new(html.borrow(), &identity);
 
-        field_populator.find_field();
+        field_populator.find_field().unwrap();
 
         assert_eq!(field_populator.value.unwrap(), "find me as well");
     }
@@ -213,14 +508,183 @@ mod tests {
         let html_string = r#"<div>find me</div> <div>as well</div>"#;
         let html = Html::parse_fragment(&html_string);
         let html = RefCell::new(&html);
-        let identity = FieldIdentity {
-            destination: Destination::new("a", ElementSelection::All(String::from(" "))),
-            destination_location: DestinationLocation::Text,
-        };
+        let identity = FieldIdentity::new(
+            Destination::new("a", ElementSelection::All(String::from(" "))),
+            DestinationLocation::Text,
+        );
         let mut field_populator = FieldPopulator::new(html.borrow(), &identity);
 
-        field_populator.find_field();
+        field_populator.find_field().unwrap();
 
         assert_eq!(field_populator.value.unwrap(), "");
     }
+
+    #[test]
+    fn test_error_on_invalid_selector() {
+        let html_string = r#"<div>find me</div>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let identity = FieldIdentity::new(
+            Destination::new("div[", ElementSelection::first()),
+            DestinationLocation::Text,
+        );
+        let mut field_populator = FieldPopulator::new(html.borrow(), &identity);
+
+        let result = field_populator.find_field();
+
+        matches::assert_matches!(result, Err(DebrisError::Selector(_)));
+    }
+
+    #[test]
+    fn test_pick_selections() {
+        let matches = vec![0, 1, 2, 3, 4, 5];
+
+        assert_eq!(vec![4], ElementSelection::Single(-2).pick(matches.clone()));
+        assert_eq!(vec![5], ElementSelection::Last.pick(matches.clone()));
+        assert!(ElementSelection::Single(6).pick(matches.clone()).is_empty());
+        assert!(ElementSelection::Single(-7)
+            .pick(matches.clone())
+            .is_empty());
+        let range = ElementSelection::Range(1, Some(3), String::new());
+        assert_eq!(vec![1, 2], range.pick(matches.clone()));
+        let range = ElementSelection::Range(-2, None, String::new());
+        assert_eq!(vec![4, 5], range.pick(matches.clone()));
+        let every = ElementSelection::Every(2, 1, String::new());
+        assert_eq!(vec![1, 3, 5], every.pick(matches.clone()));
+    }
+
+    #[test]
+    fn test_find_range_joined() {
+        let html_string = r#"<i>a</i><i>b</i><i>c</i><i>d</i>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let identity = FieldIdentity::new(
+            Destination::new("i", ElementSelection::Range(1, Some(-1), String::from("-"))),
+            DestinationLocation::Text,
+        );
+        let mut field_populator = FieldPopulator::new(html.borrow(), &identity);
+
+        field_populator.find_field().unwrap();
+
+        assert_eq!(field_populator.value.unwrap(), "b-c");
+    }
+
+    #[test]
+    fn test_extract_html_locations() {
+        let html_string =
+            r#"<div id="main" class="big red" data-x="1">HELLO <b>WORLD</b> again</div>"#;
+        let html = Html::parse_fragment(&html_string);
+        let selector = parse_selector("div").unwrap();
+        let element = html.select(&selector).next().unwrap();
+        let extracted = |location| extract(&element, &location).unwrap();
+
+        assert_eq!("main", extracted(DestinationLocation::Id));
+        assert_eq!("big red", extracted(DestinationLocation::Class));
+        assert_eq!("HELLO  again", extracted(DestinationLocation::OwnText));
+        assert_eq!(
+            "HELLO <b>WORLD</b> again",
+            extracted(DestinationLocation::InnerHtml)
+        );
+        let outer_html = extracted(DestinationLocation::OuterHtml);
+        assert!(
+            outer_html.starts_with("<div ")
+                && outer_html.ends_with(">HELLO <b>WORLD</b> again</div>")
+        );
+        assert_eq!("div", extracted(DestinationLocation::TagName));
+        assert_eq!(
+            r#"{"class":"big red","data-x":"1","id":"main"}"#,
+            extracted(DestinationLocation::AllAttributes)
+        );
+    }
+
+    #[test]
+    fn test_find_by_xpath() {
+        let html_string = r#"<table><tr><th>Price</th><td>12 EUR</td></tr></table>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let identity = FieldIdentity::new(
+            Destination::xpath(
+                "//th[text()='Price']/following-sibling::td",
+                ElementSelection::first(),
+            ),
+            DestinationLocation::Text,
+        );
+        let mut field_populator = FieldPopulator::new(html.borrow(), &identity);
+
+        field_populator.find_field().unwrap();
+
+        assert_eq!(field_populator.value.unwrap(), "12 EUR");
+    }
+
+    #[test]
+    fn test_find_by_text_filter() {
+        let html_string = r#"<table><tr><td>Name</td><td>Lamp</td></tr>
+            <tr><td>Unit
+                price</td><td>12 EUR</td></tr>
+            <tr><td>PRICE (VAT)</td><td>15 EUR</td></tr></table>"#;
+        let html = Html::parse_fragment(&html_string);
+        let find = |filter: TextFilter| {
+            let identity = FieldIdentity::new(
+                Destination::new("td", ElementSelection::All(String::from("|"))).with_text(filter),
+                DestinationLocation::Text,
+            );
+            let html = RefCell::new(&html);
+            let mut field_populator = FieldPopulator::new(html.borrow(), &identity);
+            field_populator
+                .find_field()
+                .map(|_| field_populator.value.unwrap())
+        };
+        let mut ignore_case = TextFilter::new(TextMatch::Contains, "price");
+        ignore_case.ignore_case = true;
+
+        let exact = find(TextFilter::new(TextMatch::Exact, "Unit price"));
+        let contains = find(TextFilter::new(TextMatch::Contains, "price"));
+        let regex = find(TextFilter::new(TextMatch::Regex, r"^\d+ EUR$"));
+        let invalid = find(TextFilter::new(TextMatch::Regex, "(EUR"));
+
+        assert_eq!("Unit\n                price", exact.unwrap());
+        assert_eq!("Unit\n                price", contains.unwrap());
+        assert_eq!(
+            "Unit\n                price|PRICE (VAT)",
+            find(ignore_case).unwrap()
+        );
+        assert_eq!("12 EUR|15 EUR", regex.unwrap());
+        matches::assert_matches!(invalid, Err(DebrisError::Selector(_)));
+    }
+
+    #[test]
+    fn test_find_following_label() {
+        let html_string = r#"<table><tr><th>Name</th><td>Lamp</td></tr>
+            <tr><th>Price</th><td>12 EUR</td></tr></table>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let identity = FieldIdentity::new(
+            Destination::new("th", ElementSelection::first())
+                .with_text(TextFilter::new(TextMatch::Exact, "Price")),
+            DestinationLocation::Following(Some(Selector::from("td"))),
+        );
+        let mut field_populator = FieldPopulator::new(html.borrow(), &identity);
+
+        field_populator.find_field().unwrap();
+
+        assert_eq!(field_populator.value.unwrap(), "12 EUR");
+    }
+
+    #[test]
+    fn test_find_transformed_values() {
+        let html_string = r#"<ul><li> Price:  12 EUR </li><li>Price: 15&nbsp;EUR</li></ul>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let identity = FieldIdentity::new(
+            Destination::new("li", ElementSelection::All(String::from(", "))),
+            DestinationLocation::Text,
+        )
+        .with_transform(Transform::Normalize(Normalization::Nfkc))
+        .with_transform(Transform::Capture(String::from(r"\d+"), 0));
+        let mut field_populator = FieldPopulator::new(html.borrow(), &identity);
+
+        field_populator.find_field().unwrap();
+
+        assert_eq!(field_populator.value.unwrap(), "12, 15");
+    }
 }
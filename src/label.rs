@@ -0,0 +1,142 @@
+//! Label-anchored values
+//!
+//! Pages often present their data as label/value pairs without stable classes (eg. a `dd` after
+//! the `dt` reading `Director`, or the text after `<b>Price:</b>`). The value of a label is the
+//! following sibling matching a selector, or the text following the label.
+use crate::error::Result;
+use crate::field::normalized_text;
+use crate::field::Selector;
+use scraper::ElementRef;
+use scraper::Node;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Extracts every label below an element with its value (eg. from a `dl` or a two-column table).
+pub struct Pairs {
+    /// Selects the label elements.
+    pub label: Selector,
+    /// Selects the value among the following siblings of a label. The text following the label
+    /// is its value without it.
+    pub value: Option<Selector>,
+}
+
+impl Pairs {
+    /// Pairs with the values in the following sibling elements (eg. `dt` and `dd`).
+    pub fn siblings<L: Into<Selector>, V: Into<Selector>>(label: L, value: V) -> Pairs {
+        Pairs {
+            label: label.into(),
+            value: Some(value.into()),
+        }
+    }
+
+    /// Pairs with the values in the text following the labels (eg. `<b>Price:</b> 12 EUR`).
+    pub fn following_text<L: Into<Selector>>(label: L) -> Pairs {
+        Pairs {
+            label: label.into(),
+            value: None,
+        }
+    }
+
+    /// The labels in document order with their values. A label is the visible text of its
+    /// element without a trailing `:`, labels without text are skipped.
+    pub fn extract(&self, element: &ElementRef) -> Result<Vec<(String, String)>> {
+        let mut pairs = Vec::new();
+        for label in self.label.select(element)? {
+            let name = label_text(&label);
+            if !name.is_empty() {
+                pairs.push((name, following(&label, self.value.as_ref())?));
+            }
+        }
+        Ok(pairs)
+    }
+}
+
+/// The value of the label: the text of the nearest following sibling matching the selector, or
+/// the text following the label up to a `br` without a selector. The search stops at the next
+/// label (a sibling with the tag of the label).
+pub fn following(label: &ElementRef, value: Option<&Selector>) -> Result<String> {
+    let name = label.value().name();
+    let is_boundary = |element: &ElementRef| element.value().name() == name;
+
+    match value {
+        Some(selector) => {
+            let candidates = label
+                .next_siblings()
+                .filter_map(ElementRef::wrap)
+                .take_while(|sibling| !is_boundary(sibling));
+            let matched = selector.first_match(candidates)?;
+            Ok(matched.map_or(String::new(), |element| {
+                String::from(element.text().collect::<String>().trim())
+            }))
+        }
+        None => {
+            let mut text = String::new();
+            for sibling in label.next_siblings() {
+                match sibling.value() {
+                    Node::Text(content) => text.push_str(content),
+                    Node::Element(element) if element.name() == "br" => break,
+                    Node::Element(_) => match ElementRef::wrap(sibling) {
+                        Some(element) if is_boundary(&element) => break,
+                        Some(element) => text.extend(element.text()),
+                        None => (),
+                    },
+                    _ => (),
+                }
+            }
+            Ok(String::from(text.trim()))
+        }
+    }
+}
+
+/// The visible text of a label with collapsed whitespaces and without a trailing `:`.
+fn label_text(label: &ElementRef) -> String {
+    let text = normalized_text(label);
+    String::from(text.trim_end_matches(':').trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scraper::Html;
+
+    #[test]
+    fn test_pairs_of_definition_list() {
+        let html = Html::parse_fragment(
+            r#"<dl><dt>Director</dt><dd><a>Someone</a></dd><dt>Rating</dt><dt>Year:</dt>
+            <dd> 1999 </dd><dt></dt><dd>Unlabelled</dd></dl>"#,
+        );
+
+        let pairs = Pairs::siblings("dt", "dd")
+            .extract(&html.root_element())
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                (String::from("Director"), String::from("Someone")),
+                (String::from("Rating"), String::new()),
+                (String::from("Year"), String::from("1999")),
+            ],
+            pairs
+        );
+    }
+
+    #[test]
+    fn test_pairs_of_following_text() {
+        let html = Html::parse_fragment(
+            r#"<p><b>Price:</b> 12 <i>EUR</i><br><b>Venue:</b> Budapest <b>Date:</b></p>"#,
+        );
+
+        let pairs = Pairs::following_text("b")
+            .extract(&html.root_element())
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                (String::from("Price"), String::from("12 EUR")),
+                (String::from("Venue"), String::from("Budapest")),
+                (String::from("Date"), String::new()),
+            ],
+            pairs
+        );
+    }
+}
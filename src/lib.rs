@@ -9,16 +9,41 @@
 //! from one link. However, the general case is that several identical HTML structures need to be extracted simultaneously.
 //! The [`MultiplePopulator`] is created exactly for this reason.
 //!
+mod error;
 mod path;
 mod field;
+mod xpath;
+mod label;
+mod table;
+mod transform;
+mod fetch;
+mod record;
+mod retry;
+mod throttle;
+mod robots;
+mod session;
+mod syntax;
 mod populator;
 mod parser;
+#[cfg(test)]
+mod test_util;
+
+pub use crate::error::DebrisError;
+pub use crate::error::ErrorKind;
+pub use crate::error::IoFailure;
 
 pub mod declare {
     pub use crate::path::PathBuilder;
     pub use crate::path::PathStep;
     pub use crate::field::DestinationLocation;
     pub use crate::field::Destination;
+    pub use crate::field::Selector;
+    pub use crate::field::TextFilter;
+    pub use crate::field::TextMatch;
+    pub use crate::label::Pairs;
+    pub use crate::table::Table;
+    pub use crate::transform::Normalization;
+    pub use crate::transform::Transform;
     pub use crate::field::ElementSelection;
     pub use crate::field::FieldIdentity;
     pub use crate::populator::SearchDetail;
@@ -30,12 +55,40 @@ pub mod declare {
 pub mod population {
     pub use crate::populator::SinglePopulator;
     pub use crate::populator::MultiplePopulator;
+    pub use crate::populator::LinkFailure;
+    pub use crate::populator::ErrorBudget;
+    pub use crate::fetch::Fetcher;
+    pub use crate::fetch::HttpFetcher;
+    pub use crate::fetch::MapFetcher;
+    pub use crate::fetch::FileFetcher;
+    pub use crate::fetch::local_pages;
+    pub use crate::fetch::Response;
+    pub use crate::fetch::RequestSettings;
+    pub use crate::record::RecordingFetcher;
+    pub use crate::record::ReplayFetcher;
+    pub use crate::retry::RetryFetcher;
+    pub use crate::retry::RetryPolicy;
+    pub use crate::throttle::RateLimit;
+    pub use crate::throttle::ThrottledFetcher;
+    pub use crate::robots::Robots;
+    pub use crate::robots::RobotsRules;
+    pub use crate::session::CookieJar;
+    pub use crate::session::LoginFetcher;
+    pub use crate::session::LoginForm;
+    pub use crate::session::Session;
+    pub use crate::session::SessionConfig;
 }
 
 pub mod parse {
     pub use crate::parser::Parser;
     pub use crate::parser::Config;
+    pub use crate::parser::PathDefinition;
     pub use crate::parser::Meta;
     pub use crate::parser::Populator;
+    pub use crate::parser::ParseError;
+    pub use crate::parser::parse_path;
+    pub use crate::parser::parse_field;
+    pub use crate::parser::format_path;
+    pub use crate::syntax::Span;
 }
 
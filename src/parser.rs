@@ -1,17 +1,46 @@
+use crate::error::DebrisError;
+use crate::fetch::local_pages;
+use crate::fetch::Fetcher;
+use crate::fetch::FileFetcher;
+use crate::fetch::HttpFetcher;
+use crate::fetch::RequestSettings;
 use crate::field::DestinationLocation;
 use crate::field::ElementSelection;
+use crate::field::Selector;
+use crate::field::TextFilter;
+use crate::field::TextMatch;
 use crate::field::{Destination, FieldIdentity};
+use crate::label::Pairs;
 use crate::path::PathBuilder;
 use crate::path::PathStep;
+use crate::populator::LinkFailure;
 use crate::populator::Path;
 use crate::populator::SearchDetail;
 use crate::populator::SinglePopulator;
-use crate::populator::{MultiplePopulator, Paging, PagingOptions, PagingRange};
-use colored::*;
+use crate::populator::{ErrorBudget, MultiplePopulator, Paging, PagingOptions, PagingRange};
+use crate::retry::RetryFetcher;
+use crate::retry::RetryPolicy;
+use crate::session::LoginFetcher;
+use crate::session::LoginForm;
+use crate::session::Session;
+use crate::session::SessionConfig;
+use crate::syntax;
+use crate::syntax::Command;
+use crate::syntax::Span;
+use crate::syntax::Step;
+use crate::syntax::Value;
+use crate::table::Table as HtmlTable;
+use crate::throttle::RateLimit;
+use crate::throttle::ThrottledFetcher;
+use crate::transform::Normalization;
+use crate::transform::Transform;
+use crate::xpath;
 use core::borrow::Borrow;
 use matches;
 use prettytable::{format, Attr, Cell, Row, Table};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::cmp;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
@@ -19,22 +48,60 @@ use std::fmt::Display;
 use std::fmt::Formatter;
 use std::iter::repeat;
 use std::result;
-
-const START: &str = "start";
+use std::sync::Arc;
+
+const START: &str = "START";
+const DESCEND: &str = "DESCEND";
+const FIND: &str = "FIND";
+const POPULATE: &str = "POPULATE";
+const FOREACH: &str = "FOREACH";
+const ASCEND: &str = "ASCEND";
+const CLOSEST: &str = "CLOSEST";
+const NEXT_SIBLING: &str = "NEXT_SIBLING";
+const PREV_SIBLING: &str = "PREV_SIBLING";
+const FIELD: &str = "FIELD";
+const XPATH: &str = "XPATH";
+const CONTAINS: &str = "CONTAINS";
+const MATCHES: &str = "MATCHES";
+const PAIRS: &str = "PAIRS";
+const TABLE: &str = "TABLE";
+const FOLLOWING: &str = "FOLLOWING";
 
 type Result<T> = result::Result<T, ParseError>;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+/// An error of a path string, located by its span.
 pub struct ParseError {
-    step: String,
-    error: String,
+    pub message: String,
+    pub span: Span,
+    /// Line of the span start, starting from 1.
+    pub line: usize,
+    /// Column (in characters) of the span start, starting from 1.
+    pub column: usize,
+    /// The source line the span starts in, up to the start.
+    prefix: String,
+    /// The source line the span starts in, from the start.
+    rest: String,
+    /// The number of characters underlined.
+    width: usize,
 }
 
 impl ParseError {
-    pub fn new(step: &str, message: &str) -> ParseError {
+    pub fn new(source: &str, span: Span, message: &str) -> ParseError {
+        let start = cmp::min(span.start, source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let end = cmp::max(start, cmp::min(span.end, line_end));
         ParseError {
-            step: String::from(step),
-            error: String::from(message),
+            message: String::from(message),
+            span,
+            line: source[..start].matches('\n').count() + 1,
+            column: source[line_start..start].chars().count() + 1,
+            prefix: String::from(&source[line_start..start]),
+            rest: String::from(&source[start..line_end]),
+            width: cmp::max(source[start..end].chars().count(), 1),
         }
     }
 }
@@ -42,50 +109,142 @@ impl ParseError {
 impl Error for ParseError {}
 
 impl Display for ParseError {
+    /// Renders the error with the offending part of the line underlined.
     fn fmt(&self, f: &mut Formatter) -> result::Result<(), fmt::Error> {
-        let underline: String = repeat("^").take(self.step.len()).collect();
-        write!(
-            f,
-            "{:10}: {:10} \n{:10} \n{:10}",
-            "error".red(),
-            &self.error,
-            &self.step,
-            &underline.red(),
-        )
+        let line = self.line.to_string();
+        let gutter: String = repeat(" ").take(line.len()).collect();
+        let indent: String = self
+            .prefix
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let underline: String = repeat("^").take(self.width).collect();
+
+        writeln!(f, "error: {}", self.message)?;
+        writeln!(f, "{}--> {}:{}", gutter, self.line, self.column)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}{}", line, self.prefix, self.rest)?;
+        write!(f, "{} | {}{}", gutter, indent, underline)
     }
 }
 
 #[derive(Serialize, Debug, Deserialize)]
 pub struct Config {
     pub meta: Meta,
-    pub paths: Vec<String>,
+    pub paths: Vec<PathDefinition>,
     pub fields: HashMap<String, String>,
 }
 
+#[derive(Clone, Serialize, Debug, Deserialize)]
+#[serde(untagged)]
+/// A path written in the DSL, or its structured form (eg. generated by tooling).
+pub enum PathDefinition {
+    Text(String),
+    Steps(Path),
+}
+
+impl PathDefinition {
+    pub fn resolve(&self) -> result::Result<Path, DebrisError> {
+        match self {
+            PathDefinition::Text(path_string) => resolve_path(path_string),
+            PathDefinition::Steps(path) => Ok(path.clone()),
+        }
+    }
+}
+
+impl From<String> for PathDefinition {
+    fn from(path_string: String) -> PathDefinition {
+        PathDefinition::Text(path_string)
+    }
+}
+
+impl From<Path> for PathDefinition {
+    fn from(path: Path) -> PathDefinition {
+        PathDefinition::Steps(path)
+    }
+}
+
 #[derive(Serialize, Debug, Deserialize)]
 pub struct Meta {
     pub populator: String,
-    pub link_path: Option<String>,
+    pub link_path: Option<PathDefinition>,
+    #[serde(default)]
     pub base_url: String,
+    /// A saved HTML file, a directory of them or a glob pattern to extract instead of `base_url`.
+    pub local: Option<String>,
     pub paging: Option<String>,
     pub prepend_links: Option<String>,
+    /// Abort a multiple population after this many failed links.
+    pub max_failures: Option<usize>,
+    /// Abort a multiple population, when this ratio (between 0 and 1) of the links failed.
+    pub max_failure_ratio: Option<f64>,
+    /// Repeat failed requests. Omitted settings take their default value.
+    pub retry: Option<RetryPolicy>,
+    /// Limit the requests sent to a host. Omitted settings take their default value.
+    pub rate_limit: Option<RateLimit>,
+    /// The number of threads crawling the links of a multiple population at the same time. Defaults
+    /// to the `max_concurrency` of the rate limit, or to 4 without one.
+    pub max_threads: Option<usize>,
+    /// The token matched against the user-agent groups of robots.txt. Defaults to `user_agent` or
+    /// `debris`.
+    pub robots_user_agent: Option<String>,
+    /// Crawl links disallowed by robots.txt.
+    #[serde(default)]
+    pub ignore_robots_txt: bool,
+    /// Additional headers sent with every request.
+    pub headers: Option<HashMap<String, String>>,
+    pub user_agent: Option<String>,
+    /// Cookies sent with every request by name.
+    pub cookies: Option<HashMap<String, String>>,
+    pub accept_language: Option<String>,
+    /// Share cookies between the requests, log in before the population.
+    pub session: Option<SessionConfig>,
+}
+
+impl Meta {
+    /// The request headers configured in the meta.
+    pub fn request_settings(&self) -> RequestSettings {
+        let mut settings = RequestSettings::new();
+        settings.headers = sorted_pairs(&self.headers);
+        settings.user_agent = self.user_agent.clone();
+        settings.cookies = sorted_pairs(&self.cookies);
+        settings.accept_language = self.accept_language.clone();
+        settings
+    }
+}
+
+fn sorted_pairs(map: &Option<HashMap<String, String>>) -> Vec<(String, String)> {
+    let mut pairs: Vec<(String, String)> = map
+        .iter()
+        .flat_map(|m| m.iter().map(|(k, v)| (k.clone(), v.clone())))
+        .collect();
+    pairs.sort();
+    pairs
 }
 
 pub struct Parser {
     config: Config,
+    /// Replaces the HTTP fetcher built from the meta.
+    fetcher: Option<Arc<dyn Fetcher>>,
+    session: Option<Session>,
+    /// The login form of the session, posted with the fetcher before the first request.
+    login: Option<(HttpFetcher, LoginForm)>,
 }
 
 pub enum Populator<'a> {
     Single(SinglePopulator<'a>),
     Multiple(MultiplePopulator),
+    /// One single populator for every locally stored page.
+    Local(Vec<SinglePopulator<'a>>),
 }
 
 impl<'a> Populator<'a> {
-    pub fn run(&mut self) {
+    pub fn run(&mut self) -> result::Result<(), DebrisError> {
         match self {
             Populator::Single(ref mut spop) => spop.populate(),
-            Populator::Multiple(ref mut mpop) => {
-                mpop.run();
+            Populator::Multiple(ref mut mpop) => mpop.run().map(|_| ()),
+            Populator::Local(ref mut spops) => {
+                spops.iter_mut().map(|spop| spop.populate()).collect()
             }
         }
     }
@@ -96,10 +255,24 @@ impl<'a> Populator<'a> {
         match self {
             Populator::Single(spop) => {
                 self.print_map_table(&[spop.map.clone()]);
+                self.print_map_table(&spop.records);
                 self.print_value_table(&spop.values);
             }
             Populator::Multiple(mpop) => {
-                self.print_map_table(mpop.populated_links.as_slice())
+                self.print_map_table(mpop.populated_links.as_slice());
+                self.print_map_table(&mpop.records);
+                self.print_failure_table(&mpop.failed_links);
+            }
+            Populator::Local(spops) => {
+                let maps: Vec<HashMap<String, String>> =
+                    spops.iter().map(|spop| spop.map.clone()).collect();
+                let records: Vec<HashMap<String, String>> =
+                    spops.iter().flat_map(|spop| spop.records.clone()).collect();
+                let values: Vec<String> =
+                    spops.iter().flat_map(|spop| spop.values.clone()).collect();
+                self.print_map_table(&maps);
+                self.print_map_table(&records);
+                self.print_value_table(&values);
             }
         }
     }
@@ -123,6 +296,28 @@ impl<'a> Populator<'a> {
         table.printstd();
     }
 
+    fn print_failure_table(&self, failures: &[LinkFailure]) {
+        if failures.len() == 0 {
+            return;
+        }
+        println!();
+        let mut table = Table::new();
+        table.set_titles(Row::new(vec![
+            Cell::new("Failed link").with_style(Attr::Bold),
+            Cell::new("Status").with_style(Attr::Bold),
+            Cell::new("Error").with_style(Attr::Bold),
+        ]));
+        failures.iter().for_each(|failure| {
+            let status = failure.status.map_or(String::new(), |s| s.to_string());
+            table.add_row(Row::new(vec![
+                Cell::new(&failure.url),
+                Cell::new(&status),
+                Cell::new(&failure.message),
+            ]));
+        });
+        table.printstd();
+    }
+
     fn print_value_table(&self, values: &Vec<String>) {
         let mut table = Table::new();
         table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
@@ -136,33 +331,99 @@ impl<'a> Populator<'a> {
 
 impl Parser {
     pub fn new(config: Config) -> Parser {
-        Parser { config }
+        Parser {
+            config,
+            fetcher: None,
+            session: None,
+            login: None,
+        }
     }
 
-    pub fn build(&mut self) -> Populator {
-        match self.config.meta.populator.as_str() {
-            "single" => Populator::Single(self.build_single()),
-            "multiple" => Populator::Multiple(self.build_multiple()),
-            _ => panic!("Invalid populator type. Use 'single' or 'multiple'!"),
+    /// Replace the fetcher of the built populators (eg. to record or replay a crawl). Local
+    /// sources are always read from the file system.
+    pub fn set_fetcher(&mut self, fetcher: Arc<dyn Fetcher>) {
+        self.fetcher = Some(fetcher);
+    }
+
+    /// The HTTP fetcher sending the request headers of the meta. With a session configured, the
+    /// fetcher uses its cookie jar. The login form is submitted by the built populators, before
+    /// their first request.
+    pub fn http_fetcher(&mut self) -> result::Result<HttpFetcher, DebrisError> {
+        let settings = self.config.meta.request_settings();
+        let config = match &self.config.meta.session {
+            Some(config) => config,
+            None => return HttpFetcher::with_settings(&settings),
+        };
+
+        let session = Session::open(config)?;
+        let fetcher = HttpFetcher::with_session(&settings, session.jar())?;
+        self.login = config
+            .login
+            .as_ref()
+            .map(|form| (fetcher.clone(), form.clone()));
+        self.session = Some(session);
+        Ok(fetcher)
+    }
+
+    /// Persist the cookies of the session into its cookie file (eg. after the population).
+    pub fn save_session(&self) -> result::Result<(), DebrisError> {
+        match &self.session {
+            Some(session) => session.save(),
+            None => Ok(()),
         }
     }
 
-    fn build_single(&mut self) -> SinglePopulator {
-        let mut details = SearchDetail::new();
-        for path in &self.config.paths {
-            let mut resolver = PathResolver::new(&path);
-            details.insert_path(resolver.resolve());
+    pub fn build(&mut self) -> result::Result<Populator, DebrisError> {
+        match self.config.meta.populator.as_str() {
+            "single" if self.config.meta.local.is_some() => {
+                Ok(Populator::Local(self.build_local()?))
+            }
+            "single" => Ok(Populator::Single(self.build_single()?)),
+            "multiple" => Ok(Populator::Multiple(self.build_multiple()?)),
+            _ => Err(DebrisError::Config(String::from(
+                "Invalid populator type. Use 'single' or 'multiple'!",
+            ))),
         }
-        //        for field in self.config.fields {
-        //            details.insert_field()
-        //        }
-        SinglePopulator::new(&self.config.meta.base_url, details)
     }
 
-    fn build_multiple(&mut self) -> MultiplePopulator {
-        if self.config.meta.link_path.is_none() {
-            panic!("Link path must be provided for multiple populator.");
+    fn build_single(&mut self) -> result::Result<SinglePopulator, DebrisError> {
+        let details = self.build_search_detail()?;
+        let fetcher = self.build_fetcher()?;
+        Ok(SinglePopulator::new_with_fetcher(
+            &self.config.meta.base_url,
+            details,
+            fetcher,
+        ))
+    }
+
+    fn build_local(&mut self) -> result::Result<Vec<SinglePopulator>, DebrisError> {
+        let details = self.build_search_detail()?;
+        let pages = local_pages(self.config.meta.local.as_ref().unwrap());
+        if pages.len() == 0 {
+            return Err(DebrisError::Config(String::from("No local page found")));
         }
+        let fetcher = Arc::new(FileFetcher::new());
+        Ok(pages
+            .iter()
+            .map(|page| {
+                SinglePopulator::new_with_fetcher(
+                    &page.to_string_lossy(),
+                    details.clone(),
+                    fetcher.clone(),
+                )
+            })
+            .collect())
+    }
+
+    fn build_multiple(&mut self) -> result::Result<MultiplePopulator, DebrisError> {
+        let link_path = match &self.config.meta.link_path {
+            Some(link_path) => link_path.resolve()?,
+            None => {
+                return Err(DebrisError::Config(String::from(
+                    "Link path must be provided for multiple populator.",
+                )));
+            }
+        };
         let paging = match &self.config.meta.paging {
             Some(p) => Paging::Enabled(PagingOptions {
                 extension: p.clone(),
@@ -171,296 +432,903 @@ impl Parser {
             None => Paging::Disabled,
         };
 
-        let mut link_path = PathResolver::new(self.config.meta.link_path.as_ref().unwrap());
-
-        let mut details = SearchDetail::new();
-        for path in &self.config.paths {
-            let mut resolver = PathResolver::new(&path);
-            details.insert_path(resolver.resolve());
-        }
-        MultiplePopulator::new(
+        let details = self.build_search_detail()?;
+        let mut populator = MultiplePopulator::new(
             &self.config.meta.base_url,
-            link_path.resolve(),
+            link_path,
             self.config.meta.prepend_links.clone(),
             details,
             true,
-        )
+        );
+        populator.set_fetcher(self.build_fetcher()?);
+        let max_threads = self.config.meta.max_threads.or_else(|| {
+            let limit = self.config.meta.rate_limit.as_ref();
+            limit.map(|limit| cmp::max(limit.max_concurrency, 1))
+        });
+        if let Some(max_threads) = max_threads {
+            populator.set_max_threads(max_threads);
+        }
+        match (
+            self.config.meta.max_failures,
+            self.config.meta.max_failure_ratio,
+        ) {
+            (Some(n), _) => populator.set_error_budget(ErrorBudget::Count(n)),
+            (_, Some(ratio)) => populator.set_error_budget(ErrorBudget::Ratio(ratio)),
+            _ => (),
+        }
+        let meta = &self.config.meta;
+        if meta.ignore_robots_txt {
+            populator.ignore_robots_txt();
+        } else if let Some(user_agent) =
+            meta.robots_user_agent.as_ref().or(meta.user_agent.as_ref())
+        {
+            populator.set_robots_user_agent(user_agent);
+        }
+        Ok(populator)
+    }
+
+    /// Wraps the fetcher according to the request settings of the config.
+    fn build_fetcher(&mut self) -> result::Result<Arc<dyn Fetcher>, DebrisError> {
+        let mut fetcher: Arc<dyn Fetcher> = match self.fetcher.clone() {
+            Some(fetcher) => fetcher,
+            None => Arc::new(self.http_fetcher()?),
+        };
+        if let (Some(session), Some((form_fetcher, form))) = (&self.session, &self.login) {
+            fetcher = Arc::new(LoginFetcher::new(
+                fetcher,
+                session.clone(),
+                form_fetcher.clone(),
+                form.clone(),
+            ));
+        }
+        let meta = &self.config.meta;
+        Ok(match (&meta.rate_limit, &meta.retry) {
+            (Some(limit), Some(policy)) => {
+                let throttled = Arc::new(ThrottledFetcher::new(fetcher, limit.clone()));
+                Arc::new(RetryFetcher::throttled(throttled, policy.clone()))
+            }
+            (Some(limit), None) => Arc::new(ThrottledFetcher::new(fetcher, limit.clone())),
+            (None, Some(policy)) => Arc::new(RetryFetcher::new(fetcher, policy.clone())),
+            (None, None) => fetcher,
+        })
+    }
+
+    fn build_search_detail(&self) -> result::Result<SearchDetail<'static>, DebrisError> {
+        let mut details = SearchDetail::new();
+        for path in &self.config.paths {
+            details.insert_path(path.resolve()?);
+        }
+        for (field_name, definition) in &self.config.fields {
+            let (name, identity) = parse_field(definition)
+                .map_err(|errors| config_error(&format!("field '{}'", field_name), &errors))?;
+            if let Some(name) = name.filter(|name| name != field_name) {
+                return Err(DebrisError::Config(format!(
+                    "Field '{}' is named '{}' in its definition",
+                    field_name, name
+                )));
+            }
+            details.insert_field_identity(field_name, identity);
+        }
+        Ok(details)
+    }
+}
+
+/// Parses a path string, failing with every error found in it ordered by position.
+pub fn parse_path(path_string: &str) -> result::Result<Path, Vec<ParseError>> {
+    PathResolver::new(path_string).resolve()
+}
+
+/// Formats a path into the canonical DSL text, that parses back to the same path. The fields of
+/// a populate step are grouped into a block ordered by name.
+pub fn format_path(path: &[PathStep]) -> String {
+    let steps: Vec<String> = path
+        .iter()
+        .filter_map(|step| match step {
+            PathStep::Start(destination) => {
+                Some(format!("{}({})", START, format_destination(destination)))
+            }
+            PathStep::Descend(destination) => {
+                Some(format!("{}({})", DESCEND, format_destination(destination)))
+            }
+            PathStep::ForEach(destination) => Some(format_for_each(destination)),
+            PathStep::Ascend(levels) => Some(format!("{}(LEVELS: {})", ASCEND, levels)),
+            PathStep::Closest(selector) => Some(format_selector_step(CLOSEST, selector)),
+            PathStep::NextSibling(selector) => Some(format_selector_step(NEXT_SIBLING, selector)),
+            PathStep::PrevSibling(selector) => Some(format_selector_step(PREV_SIBLING, selector)),
+            PathStep::Find(identity) => Some(format!("{}({})", FIND, format_identity(identity))),
+            PathStep::Table(table) => Some(format_table(table)),
+            PathStep::Pairs(pairs) => Some(match &pairs.value {
+                Some(value) => format!(
+                    "{}(LABEL: {}, VALUE: {})",
+                    PAIRS,
+                    format_selector(&pairs.label),
+                    format_selector(value)
+                ),
+                None => format!("{}(LABEL: {})", PAIRS, format_selector(&pairs.label)),
+            }),
+            PathStep::Populate(fields) => {
+                let mut fields: Vec<(&String, &FieldIdentity)> = fields.iter().collect();
+                fields.sort_by_key(|(name, _)| name.as_str());
+                let commands: Vec<String> = fields
+                    .iter()
+                    .map(|(name, identity)| {
+                        format!(
+                            "{}(NAME: {}, {})",
+                            POPULATE,
+                            syntax::format_value(name),
+                            format_identity(identity)
+                        )
+                    })
+                    .collect();
+                match commands.len() {
+                    0 => None,
+                    1 => Some(commands[0].clone()),
+                    _ => Some(format!("{{ {} }}", commands.join(", "))),
+                }
+            }
+        })
+        .collect();
+    steps.join(" -> ")
+}
+
+fn format_table(table: &HtmlTable) -> String {
+    let mut renames: Vec<(&String, &String)> = table.rename.iter().collect();
+    renames.sort();
+    let arguments: Vec<String> = table
+        .columns
+        .iter()
+        .map(|column| format!("COLUMN: {}", syntax::format_value(column)))
+        .chain(renames.iter().map(|(header, name)| {
+            let rename = format!("{} = {}", header, name);
+            format!("RENAME: {}", syntax::format_value(&rename))
+        }))
+        .collect();
+    format!("{}({})", TABLE, arguments.join(", "))
+}
+
+/// The selection of a FOREACH is omitted, when it keeps every element.
+fn format_for_each(destination: &Destination) -> String {
+    match destination {
+        Destination(selector, ElementSelection::All(delimiter), filter) if delimiter.is_empty() => {
+            let filter = filter.as_ref().map_or(String::new(), format_text_filter);
+            format!(
+                "{}(SELECTOR: {}{})",
+                FOREACH,
+                format_selector(selector),
+                filter
+            )
+        }
+        _ => format!("{}({})", FOREACH, format_destination(destination)),
+    }
+}
+
+fn format_selector_step(command: &str, selector: &Selector) -> String {
+    format!("{}(SELECTOR: {})", command, format_selector(selector))
+}
+
+fn format_selector(selector: &Selector) -> String {
+    match selector {
+        Selector::XPath(xpath) => syntax::format_call(XPATH, xpath),
+        Selector::Css(css) => {
+            let formatted = syntax::format_value(css);
+            // A raw CSS selector looking like an XPath call would parse back as one
+            let value = syntax::Value {
+                text: formatted.clone(),
+                quoted: false,
+                span: Span::new(0, formatted.len()),
+            };
+            match value.call() {
+                Some((name, _)) if name == XPATH => syntax::quote(css),
+                _ => formatted,
+            }
+        }
+    }
+}
+
+fn format_destination(destination: &Destination) -> String {
+    let filter = match &destination.2 {
+        Some(filter) => format_text_filter(filter),
+        None => String::new(),
+    };
+    format!(
+        "SELECTOR: {}, SELECT: {}{}",
+        format_selector(&destination.0),
+        format_selection(&destination.1),
+        filter
+    )
+}
+
+fn format_text_filter(filter: &TextFilter) -> String {
+    let text = match filter.mode {
+        TextMatch::Contains => syntax::format_call(CONTAINS, &filter.text),
+        TextMatch::Regex => syntax::format_call(MATCHES, &filter.text),
+        TextMatch::Exact => {
+            let formatted = syntax::format_value(&filter.text);
+            // A raw text looking like a call would parse back as a different kind of filter
+            match formatted.ends_with(')') {
+                true => syntax::quote(&filter.text),
+                false => formatted,
+            }
+        }
+    };
+    match filter.ignore_case {
+        true => format!(", TEXT: {}, IGNORE_CASE: TRUE", text),
+        false => format!(", TEXT: {}", text),
+    }
+}
+
+fn format_identity(identity: &FieldIdentity) -> String {
+    let transforms: String = identity
+        .transforms
+        .iter()
+        .map(|transform| format!(", TRANSFORM: {}", format_transform(transform)))
+        .collect();
+    format!(
+        "{}, LOC: {}{}",
+        format_destination(&identity.destination),
+        format_location(&identity.destination_location),
+        transforms
+    )
+}
+
+fn format_transform(transform: &Transform) -> String {
+    let call = |name: &str, arguments: &[&str]| {
+        let arguments: Vec<String> = arguments
+            .iter()
+            .map(|argument| syntax::format_value(argument))
+            .collect();
+        format!("{}({})", name, arguments.join(", "))
+    };
+    match transform {
+        Transform::Trim => String::from("TRIM"),
+        Transform::CollapseWhitespace => String::from("COLLAPSE_WHITESPACE"),
+        Transform::Lowercase => String::from("LOWERCASE"),
+        Transform::Uppercase => String::from("UPPERCASE"),
+        Transform::DecodeEntities => String::from("DECODE_ENTITIES"),
+        Transform::Capture(pattern, group) => call("CAPTURE", &[pattern, &group.to_string()]),
+        Transform::Replace(pattern, replacement) => call("REPLACE", &[pattern, replacement]),
+        Transform::Split(separator, n) => call("SPLIT", &[separator, &n.to_string()]),
+        Transform::Normalize(form) => {
+            let form = format!("{:?}", form).to_uppercase();
+            call("NORMALIZE", &[&form])
+        }
+        Transform::Prefix(prefix) => call("PREFIX", &[prefix]),
+        Transform::Suffix(suffix) => call("SUFFIX", &[suffix]),
+        Transform::Substring(start, None) => call("SUBSTRING", &[&start.to_string()]),
+        Transform::Substring(start, Some(end)) => {
+            call("SUBSTRING", &[&start.to_string(), &end.to_string()])
+        }
     }
 }
 
+fn format_selection(selection: &ElementSelection) -> String {
+    let delimiter = match selection.delimiter() {
+        Some(delimiter) if !delimiter.is_empty() => {
+            format!(", DELIMITER: {}", syntax::format_value(delimiter))
+        }
+        _ => String::new(),
+    };
+    match selection {
+        ElementSelection::Single(n) => n.to_string(),
+        ElementSelection::Last => String::from("LAST"),
+        ElementSelection::All(delimiter) if delimiter.is_empty() => String::from("ALL"),
+        ElementSelection::All(delimiter) => syntax::format_call("ALL", delimiter),
+        ElementSelection::Range(start, end, _) => {
+            let end = end.map_or(String::new(), |end| end.to_string());
+            format!("{}..{}{}", start, end, delimiter)
+        }
+        ElementSelection::Every(step, 0, _) => format!("EVERY({}){}", step, delimiter),
+        ElementSelection::Every(step, offset, _) => {
+            format!("EVERY({}, {}){}", step, offset, delimiter)
+        }
+    }
+}
+
+fn format_location(location: &DestinationLocation) -> String {
+    match location {
+        DestinationLocation::Text => String::from("TEXT"),
+        DestinationLocation::Attr(attribute) => syntax::format_call("ATTR", attribute),
+        DestinationLocation::Id => String::from("ID"),
+        DestinationLocation::Class => String::from("CLASS"),
+        DestinationLocation::OwnText => String::from("OWN_TEXT"),
+        DestinationLocation::InnerHtml => String::from("INNER_HTML"),
+        DestinationLocation::OuterHtml => String::from("OUTER_HTML"),
+        DestinationLocation::TagName => String::from("TAG_NAME"),
+        DestinationLocation::AllAttributes => String::from("ATTRIBUTES"),
+        DestinationLocation::Following(None) => String::from(FOLLOWING),
+        DestinationLocation::Following(Some(Selector::Css(css))) => {
+            syntax::format_call(FOLLOWING, css)
+        }
+        DestinationLocation::Following(Some(selector)) => {
+            format!("{}({})", FOLLOWING, format_selector(selector))
+        }
+    }
+}
+
+/// Parses a `FIELD(...)` definition into its optional name and the identity of the field.
+pub fn parse_field(
+    field_string: &str,
+) -> result::Result<(Option<String>, FieldIdentity), Vec<ParseError>> {
+    PathResolver::new(field_string).resolve_field()
+}
+
+/// Resolves a path string into a config error rendering every parse error.
+fn resolve_path(path_string: &str) -> result::Result<Path, DebrisError> {
+    parse_path(path_string).map_err(|errors| config_error("path", &errors))
+}
+
+fn config_error(source: &str, errors: &[ParseError]) -> DebrisError {
+    let rendered: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    DebrisError::Config(format!(
+        "{} error(s) in {}\n\n{}",
+        errors.len(),
+        source,
+        rendered.join("\n\n")
+    ))
+}
+
 struct PathResolver {
-    path_tokens: Vec<String>,
+    source: String,
     path: PathBuilder,
-    current: usize,
-    map_buffer: Option<HashMap<String, FieldIdentity>>,
-    pub errors: Vec<ParseError>,
+    errors: Vec<ParseError>,
 }
 
 impl PathResolver {
     pub fn new(path_string: &str) -> PathResolver {
-        let path_tokens: Vec<String> = path_string
-            .replace(" ", "")
-            .split("->")
-            .map(|p| String::from(p))
-            .collect();
         let path = PathBuilder::new();
         let errors = Vec::<ParseError>::new();
-        let map_buffer = HashMap::new();
         PathResolver {
-            path_tokens,
-            current: 0,
+            source: String::from(path_string),
             path,
-            map_buffer: Some(map_buffer),
             errors,
         }
     }
 
-    pub fn resolve(&mut self) -> Path {
-        let first = self.query_current_step();
-        if first.to_lowercase().starts_with("start") {
-            if let Err(error) = self.resolve_start() {
+    pub fn resolve(&mut self) -> result::Result<Path, Vec<ParseError>> {
+        let (steps, syntax_errors) = syntax::parse(&self.source);
+        for error in syntax_errors {
+            let error = self.error_at(error.span, &error.message);
+            self.errors.push(error);
+        }
+
+        let starts_with_start = match steps.first() {
+            Some(Step::Command(first)) if first.name == START => true,
+            Some(first) => {
+                let error = self.error_at(
+                    first.first().name_span,
+                    "First path command must be a start!",
+                );
                 self.errors.push(error);
+                false
             }
-            self.current += 1;
-        } else {
-            self.errors.push(ParseError::new(
-                &first,
-                "First path command must be a start!",
-            ));
-        }
+            None => false,
+        };
 
-        while self.current + 1 <= self.path_tokens.len() {
-            match self.path_tokens.get(self.current) {
-                Some(token) => {
-                    if token.to_lowercase().starts_with("descend") {
-                        if let Err(error) = self.resolve_descend() {
-                            self.errors.push(error)
-                        }
-                    } else if token.to_lowercase().starts_with("find") {
-                        if let Err(error) = self.resolve_find() {
-                            self.errors.push(error)
-                        }
-                    } else if token.to_lowercase().starts_with("populate") {
-                        if let Err(error) = self.resolve_populate() {
-                            self.errors.push(error)
-                        }
-                    } else {
-                        self.errors.push(ParseError::new(&token, "Invalid command"))
-                    }
-                }
-                None => (),
+        for (i, step) in steps.iter().enumerate() {
+            match step {
+                Step::Command(command) => self.resolve_command(command, i == 0),
+                Step::Block(commands, _) => self.resolve_block(commands),
             }
-            self.current += 1;
         }
 
-        if self.errors.len() != 0 {
-            self.errors.iter().for_each(|error| println!("{}", error));
+        if starts_with_start && self.path.build().len() == 0 {
+            let error = self.error(steps[0].first(), "Path must begin with a valid start");
+            self.errors.push(error);
         }
 
-        if let Some(map) = &self.map_buffer {
-            if map.len() != 0 {
-                self.path.populate(self.map_buffer.take().unwrap());
-            }
+        if self.errors.len() != 0 {
+            let mut errors = self.errors.clone();
+            errors.sort_by_key(|error| error.span.start);
+            return Err(errors);
         }
-        self.path.build()
+        Ok(self.path.build())
     }
 
-    fn resolve_start(&mut self) -> Result<()> {
-        let args: HashMap<String, String> = self.extract_args()?;
-        let selector = self.extract_selector_string(&args)?;
-        let select = self.extract_select_number(&args)?;
-        self.path.start(Destination::new(
-            &selector,
-            ElementSelection::Single(select),
-        ));
-        Ok(())
-    }
-
-    fn resolve_descend(&mut self) -> Result<()> {
-        let args = self.extract_args()?;
-        let selector = self.extract_selector_string(&args)?;
-        let select = self.extract_select_number(&args)?;
-        self.path.descend(&selector, select);
-        Ok(())
-    }
-
-    fn resolve_populate(&mut self) -> Result<()> {
-        let args = self.extract_args()?;
-        let selector = self.extract_selector_string(&args)?;
-        let location = self.extract_location(&args)?;
-        let field = self.extract_field_name(&args)?;
-        let select = match args.get("select") {
-            Some(select) => {
-                if select.to_lowercase().starts_with("all") {
-                    ElementSelection::All(Self::extract_between_brackets(&select)?)
-                } else {
-                    ElementSelection::Single(self.extract_select_number(&args)?)
+    fn resolve_command(&mut self, command: &Command, first: bool) {
+        match command.name.as_str() {
+            START if first => self.resolve_start(command),
+            START => self.errors.push(self.error_at(
+                command.name_span,
+                "Start is only allowed as the first command",
+            )),
+            DESCEND => self.resolve_descend(command),
+            FIND => self.resolve_find(command),
+            POPULATE => {
+                if let Some((field, identity)) = self.resolve_populate(command) {
+                    let mut fields = HashMap::new();
+                    fields.insert(field, identity);
+                    self.path.populate(fields);
                 }
             }
-            None => ElementSelection::Single(0),
+            FOREACH => self.resolve_foreach(command),
+            ASCEND => self.resolve_ascend(command),
+            CLOSEST => self.resolve_closest(command),
+            NEXT_SIBLING | PREV_SIBLING => self.resolve_sibling(command),
+            PAIRS => self.resolve_pairs(command),
+            TABLE => self.resolve_table(command),
+            _ => self
+                .errors
+                .push(self.error_at(command.name_span, "Invalid command")),
         };
+    }
 
-        let identity = FieldIdentity {
-            destination: Destination::new(&selector, select),
-            destination_location: location,
-        };
-        if let Some(ref mut map) = self.map_buffer {
-            map.insert(field, identity);
-        }
-        Ok(())
-    }
-
-    fn resolve_find(&mut self) -> Result<()> {
-        let args = self.extract_args()?;
-        let selector = self.extract_selector_string(&args)?;
-        let location = self.extract_location(&args)?;
-        match args.get("select") {
-            Some(select) => {
-                if select.to_lowercase().starts_with("all") {
-                    self.path.find_all(&selector, location);
-                } else {
-                    self.path
-                        .find_one(&selector, self.extract_select_number(&args)?, location);
+    /// The POPULATE commands of a block share a single step.
+    fn resolve_block(&mut self, commands: &[Command]) {
+        let mut fields = HashMap::new();
+        for command in commands {
+            if command.name != POPULATE {
+                let error =
+                    self.error_at(command.name_span, "Only POPULATE commands can be grouped");
+                self.errors.push(error);
+                continue;
+            }
+            if let Some((field, identity)) = self.resolve_populate(command) {
+                fields.insert(field, identity);
+            }
+        }
+        self.path.populate(fields);
+    }
+
+    /// A field is a single `FIELD` command, its name is optional (the config names its fields).
+    pub fn resolve_field(
+        &mut self,
+    ) -> result::Result<(Option<String>, FieldIdentity), Vec<ParseError>> {
+        let (steps, syntax_errors) = syntax::parse(&self.source);
+        for error in syntax_errors {
+            let error = self.error_at(error.span, &error.message);
+            self.errors.push(error);
+        }
+
+        let mut field = None;
+        for (i, step) in steps.iter().enumerate() {
+            match step {
+                Step::Command(command) if i == 0 && command.name == FIELD => {
+                    field = self.resolve_field_command(command)
                 }
+                _ if i == 0 => self
+                    .errors
+                    .push(self.error_at(step.first().name_span, "Expected a FIELD command")),
+                _ => self
+                    .errors
+                    .push(self.error_at(step.span(), "A field consists of a single command")),
             }
-            None => (),
+        }
+
+        match field {
+            Some(field) if self.errors.len() == 0 => Ok(field),
+            _ => {
+                let mut errors = self.errors.clone();
+                errors.sort_by_key(|error| error.span.start);
+                Err(errors)
+            }
+        }
+    }
+
+    fn resolve_field_command(
+        &mut self,
+        command: &Command,
+    ) -> Option<(Option<String>, FieldIdentity)> {
+        let name = command.argument("name").map(|name| name.value.text.clone());
+        Some((name, self.resolve_identity(command)?))
+    }
+
+    /// The identity of a FIELD, FIND or POPULATE command.
+    fn resolve_identity(&mut self, command: &Command) -> Option<FieldIdentity> {
+        let selector = self.check(self.extract_selector(command));
+        let location = self.check(self.extract_location(command));
+        let select = self.check(self.extract_selection(command));
+        let filter = self.check(self.extract_text_filter(command));
+        let transforms = self.check(self.extract_transforms(command));
+
+        Some(FieldIdentity {
+            destination: Destination(selector?, select?, filter?),
+            destination_location: location?,
+            transforms: transforms?,
+        })
+    }
+
+    /// Every argument of a command is checked, so all of its errors are reported at once.
+    fn check<T>(&mut self, extracted: Result<T>) -> Option<T> {
+        match extracted {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.errors.push(error);
+                None
+            }
+        }
+    }
+
+    fn resolve_start(&mut self, command: &Command) {
+        let selector = self.check(self.extract_selector(command));
+        let select = self.check(self.extract_selection(command));
+        let filter = self.check(self.extract_text_filter(command));
+        if let (Some(selector), Some(select), Some(filter)) = (selector, select, filter) {
+            self.path.start(Destination(selector, select, filter));
+        }
+    }
+
+    fn resolve_descend(&mut self, command: &Command) {
+        let selector = self.check(self.extract_selector(command));
+        let select = self.check(self.extract_single_selection(command));
+        let filter = self.check(self.extract_text_filter(command));
+        if let (Some(selector), Some(select), Some(filter)) = (selector, select, filter) {
+            self.path
+                .descend_into(Destination(selector, select, filter));
+        }
+    }
+
+    /// Every matched element is iterated without `SELECT`.
+    fn resolve_foreach(&mut self, command: &Command) {
+        let selector = self.check(self.extract_selector(command));
+        let select = match command.argument("select") {
+            Some(_) => self.check(self.extract_selection(command)),
+            None => Some(ElementSelection::All(String::new())),
         };
+        let filter = self.check(self.extract_text_filter(command));
+        if let (Some(selector), Some(select), Some(filter)) = (selector, select, filter) {
+            self.path.for_each_of(Destination(selector, select, filter));
+        }
+    }
 
-        Ok(())
+    fn resolve_ascend(&mut self, command: &Command) {
+        let levels = match command.argument("levels") {
+            Some(levels) => match levels.value.text.parse::<usize>() {
+                Ok(n) => n,
+                Err(_) => {
+                    let error = self.error_at(levels.value.span, "Invalid number of levels");
+                    return self.errors.push(error);
+                }
+            },
+            None => 1,
+        };
+        self.path.ascend(levels);
     }
 
-    fn extract_args(&self) -> Result<HashMap<String, String>> {
-        let token = self.path_tokens.get(self.current).unwrap();
-        let token = Self::extract_between_brackets(&token)?;
+    fn resolve_closest(&mut self, command: &Command) {
+        if let Some(selector) = self.check(self.extract_selector(command)) {
+            self.path.closest(selector);
+        }
+    }
 
-        let args: Vec<&str> = token.split(",").collect();
-        let args: Vec<Vec<&str>> = args
-            .iter()
-            .map(|a| a.split(':').collect::<Vec<&str>>())
-            .collect();
+    /// Any sibling element is matched without a selector.
+    fn resolve_sibling(&mut self, command: &Command) {
+        let selector = match command.argument("selector") {
+            Some(_) => match self.check(self.extract_selector(command)) {
+                Some(selector) => selector,
+                None => return,
+            },
+            None => Selector::from("*"),
+        };
+        match command.name.as_str() {
+            NEXT_SIBLING => self.path.next_sibling(selector),
+            _ => self.path.prev_sibling(selector),
+        };
+    }
 
-        let mut arg_map: HashMap<String, String> = HashMap::new();
-        args.iter().for_each(|a| {
-            &arg_map.insert(
-                a.get(0).unwrap().to_lowercase(),
-                a.get(1).unwrap().to_lowercase(),
-            );
-        });
-        Ok(arg_map)
+    /// The values are the text following the labels without a `VALUE` selector.
+    fn resolve_pairs(&mut self, command: &Command) {
+        let label = match command.argument("label") {
+            Some(label) => self.check(self.selector_value(&label.value)),
+            None => self.check(Err(self.error(command, "Missing label selector"))),
+        };
+        let value = match command.argument("value") {
+            Some(value) => self.check(self.selector_value(&value.value)).map(Some),
+            None => Some(None),
+        };
+        if let (Some(label), Some(value)) = (label, value) {
+            self.path.pairs(Pairs { label, value });
+        }
     }
 
-    fn extract_field_name(&self, args: &HashMap<String, String>) -> Result<String> {
-        let field_name = match args.get("name") {
-            Some(name) => name,
-            None => {
-                return Err(ParseError::new(
-                    &self.query_current_step(),
-                    "Missing field name",
-                ));
+    /// Every `COLUMN` is kept, a `RENAME` like `Unit price = price` names a column differently.
+    fn resolve_table(&mut self, command: &Command) {
+        let mut table = HtmlTable::new();
+        for column in command.arguments_named("column") {
+            table.column(&column.value.text);
+        }
+        for rename in command.arguments_named("rename") {
+            let text = &rename.value.text;
+            match text.rfind('=') {
+                Some(i) if !text[..i].trim().is_empty() && !text[i + 1..].trim().is_empty() => {
+                    table.rename(text[..i].trim(), text[i + 1..].trim());
+                }
+                _ => {
+                    let error = self.error_at(rename.value.span, "Expected `header = name`");
+                    self.errors.push(error);
+                }
             }
+        }
+        self.path.table(table.build());
+    }
+
+    fn resolve_populate(&mut self, command: &Command) -> Option<(String, FieldIdentity)> {
+        let field = self.check(self.extract_field_name(command));
+        let identity = self.resolve_identity(command);
+        Some((field?, identity?))
+    }
+
+    fn resolve_find(&mut self, command: &Command) {
+        if let Some(identity) = self.resolve_identity(command) {
+            self.path.find_identity(identity);
+        }
+    }
+
+    fn extract_field_name(&self, command: &Command) -> Result<String> {
+        match command.argument("name") {
+            Some(name) => Ok(name.value.text.clone()),
+            None => Err(self.error(command, "Missing field name")),
+        }
+    }
+
+    fn extract_location(&self, command: &Command) -> Result<DestinationLocation> {
+        let location = match command.argument("loc") {
+            Some(location) => &location.value,
+            None => return Err(self.error(command, "Missing location")),
         };
-        Ok(field_name.clone())
-    }
-    fn extract_location(&self, args: &HashMap<String, String>) -> Result<DestinationLocation> {
-        let location = match args.get("loc") {
-            Some(loc) => {
-                if loc.to_lowercase().starts_with("text") {
-                    DestinationLocation::Text
-                } else if loc.to_lowercase().starts_with("attr") {
-                    DestinationLocation::Attr(Self::extract_between_brackets(loc)?)
-                } else {
-                    return Err(ParseError::new(
-                        &self.query_current_step(),
-                        "Invalid location",
-                    ));
-                }
+        let keywords = [
+            ("text", DestinationLocation::Text),
+            ("own_text", DestinationLocation::OwnText),
+            ("inner_html", DestinationLocation::InnerHtml),
+            ("outer_html", DestinationLocation::OuterHtml),
+            ("tag_name", DestinationLocation::TagName),
+            ("id", DestinationLocation::Id),
+            ("class", DestinationLocation::Class),
+            ("attributes", DestinationLocation::AllAttributes),
+            ("following", DestinationLocation::Following(None)),
+        ];
+        for (keyword, destination_location) in keywords.iter() {
+            if location.is_keyword(keyword) {
+                return Ok(destination_location.clone());
             }
-            None => {
-                return Err(ParseError::new(
-                    &self.query_current_step(),
-                    "Missing location",
-                ));
+        }
+        match location.call() {
+            // HTML attribute names are case insensitive, the parsed document stores them lowercase
+            Some((name, attribute)) if name == "ATTR" => {
+                Ok(DestinationLocation::Attr(attribute.trim().to_lowercase()))
+            }
+            // The selector keeps the position of the whole location for the errors
+            Some((name, selector)) if name == FOLLOWING => {
+                let value = Value {
+                    text: selector,
+                    quoted: false,
+                    span: location.span,
+                };
+                Ok(DestinationLocation::Following(Some(
+                    self.selector_value(&value)?,
+                )))
             }
+            _ => Err(self.error_at(location.span, "Invalid location")),
+        }
+    }
+
+    fn extract_selector(&self, command: &Command) -> Result<Selector> {
+        match command.argument("selector") {
+            Some(selector) => self.selector_value(&selector.value),
+            None => Err(self.error(command, "Missing selector string")),
+        }
+    }
+
+    /// A CSS selector, or an XPath expression like `XPATH(//td[2])`.
+    fn selector_value(&self, selector: &Value) -> Result<Selector> {
+        match selector.call() {
+            Some((name, expression)) if name == XPATH => match xpath::compile(&expression) {
+                Ok(_) => Ok(Selector::XPath(expression)),
+                Err(_) => Err(self.error_at(selector.span, "Invalid XPath")),
+            },
+            _ => Ok(Selector::Css(selector.text.clone())),
+        }
+    }
+
+    /// A number (negative counts from the end), `LAST`, a range like `1..5`, `EVERY(step)`,
+    /// `EVERY(step, offset)` or `ALL(delimiter)`. The delimiter might be omitted with the
+    /// parentheses, the other selections of several elements take it from `DELIMITER`.
+    fn extract_selection(&self, command: &Command) -> Result<ElementSelection> {
+        let select = match command.argument("select") {
+            Some(select) => &select.value,
+            None => return Ok(ElementSelection::first()),
         };
+        let delimiter = command
+            .argument("delimiter")
+            .map_or(String::new(), |delimiter| delimiter.value.text.clone());
+        let invalid = || self.error_at(select.span, "Invalid select element number");
 
-        Ok(location)
+        if select.is_keyword("all") {
+            return Ok(ElementSelection::All(delimiter));
+        }
+        if select.is_keyword("last") {
+            return Ok(ElementSelection::Last);
+        }
+        match select.call() {
+            Some((ref name, ref inner)) if name == "ALL" => {
+                return Ok(ElementSelection::All(inner.clone()));
+            }
+            Some((ref name, ref inner)) if name == "EVERY" => {
+                let numbers: Vec<&str> = inner.split(',').map(str::trim).collect();
+                let parsed: Vec<usize> = numbers.iter().filter_map(|n| n.parse().ok()).collect();
+                return match parsed.as_slice() {
+                    [step] if numbers.len() == 1 && *step > 0 => {
+                        Ok(ElementSelection::Every(*step, 0, delimiter))
+                    }
+                    [step, offset] if numbers.len() == 2 && *step > 0 => {
+                        Ok(ElementSelection::Every(*step, *offset, delimiter))
+                    }
+                    _ => Err(invalid()),
+                };
+            }
+            _ => (),
+        }
+        if let Some(separator) = select.text.find("..") {
+            let (start, end) = (&select.text[..separator], &select.text[separator + 2..]);
+            let start = match start.trim() {
+                "" => Ok(0),
+                start => start.parse::<i32>(),
+            };
+            let end = match end.trim() {
+                "" => Ok(None),
+                end => end.parse::<i32>().map(Some),
+            };
+            return match (start, end) {
+                (Ok(start), Ok(end)) => Ok(ElementSelection::Range(start, end, delimiter)),
+                _ => Err(invalid()),
+            };
+        }
+        match select.text.parse::<i32>() {
+            Ok(n) => Ok(ElementSelection::Single(n)),
+            Err(_) => Err(invalid()),
+        }
     }
 
-    fn extract_selector_string(&self, args: &HashMap<String, String>) -> Result<String> {
-        match args.get("selector") {
-            Some(s) => Ok(s.clone()),
-            None => {
-                return Err(ParseError::new(
-                    &self.query_current_step(),
-                    "Missing selector string",
-                ));
+    /// A selection of a single element (eg. to descend on).
+    fn extract_single_selection(&self, command: &Command) -> Result<ElementSelection> {
+        let selection = self.extract_selection(command)?;
+        match (selection.is_multiple(), command.argument("select")) {
+            (true, Some(select)) => {
+                Err(self.error_at(select.value.span, "Only a single element can be selected"))
             }
+            _ => Ok(selection),
         }
     }
 
-    fn extract_select_number(&self, args: &HashMap<String, String>) -> Result<i32> {
-        match args.get("select") {
-            Some(s) => match s.parse::<i32>() {
-                Ok(n) => Ok(n),
-                Err(_) => {
-                    return Err(ParseError::new(
-                        &self.query_current_step(),
-                        "Invalid select element number",
-                    ));
-                }
-            },
-            None => Ok(0),
+    /// The text of the matched elements equals `TEXT`, contains it with `CONTAINS(text)`, or
+    /// matches the `MATCHES(regex)`. `IGNORE_CASE: TRUE` compares case insensitively.
+    fn extract_text_filter(&self, command: &Command) -> Result<Option<TextFilter>> {
+        let ignore_case = match command.argument("ignore_case") {
+            Some(flag) if flag.value.is_keyword("true") => true,
+            Some(flag) if flag.value.is_keyword("false") => false,
+            Some(flag) => return Err(self.error_at(flag.value.span, "Expected TRUE or FALSE")),
+            None => false,
+        };
+        let text = match command.argument("text") {
+            Some(text) => &text.value,
+            None if ignore_case => return Err(self.error(command, "Missing text to match")),
+            None => return Ok(None),
+        };
+
+        let mut filter = match text.call() {
+            Some((name, inner)) if name == CONTAINS => TextFilter::new(TextMatch::Contains, &inner),
+            Some((name, inner)) if name == MATCHES => TextFilter::new(TextMatch::Regex, &inner),
+            _ => TextFilter::new(TextMatch::Exact, &text.text),
+        };
+        filter.ignore_case = ignore_case;
+        match filter.compile() {
+            Ok(_) => Ok(Some(filter)),
+            Err(_) => Err(self.error_at(text.span, "Invalid regular expression")),
         }
     }
 
-    fn extract_between_brackets(token: &str) -> Result<String> {
-        let starting_bracket_index = match token.find("(") {
-            Some(i) => i,
-            None => {
-                return Err(ParseError::new(
-                    &token,
-                    "Command must start with opening bracket",
-                ));
+    /// The `TRANSFORM` arguments in order (eg. `TRANSFORM: TRIM, TRANSFORM: CAPTURE(\d+)`).
+    fn extract_transforms(&self, command: &Command) -> Result<Vec<Transform>> {
+        let mut transforms = Vec::new();
+        for argument in command.arguments_named("transform") {
+            let transform = self.transform_value(&argument.value)?;
+            if transform.regex().is_err() {
+                return Err(self.error_at(argument.value.span, "Invalid regular expression"));
             }
-        };
+            transforms.push(transform);
+        }
+        Ok(transforms)
+    }
+
+    fn transform_value(&self, value: &Value) -> Result<Transform> {
+        let keywords = [
+            ("trim", Transform::Trim),
+            ("collapse_whitespace", Transform::CollapseWhitespace),
+            ("lowercase", Transform::Lowercase),
+            ("uppercase", Transform::Uppercase),
+            ("decode_entities", Transform::DecodeEntities),
+        ];
+        for (keyword, transform) in keywords.iter() {
+            if value.is_keyword(keyword) {
+                return Ok(transform.clone());
+            }
+        }
 
-        let closing_bracket_index = match token.rfind(")") {
-            Some(i) => i,
-            None => return Err(ParseError::new(&token, "Unclosed parentheses")),
+        let invalid = || self.error_at(value.span, "Invalid transform");
+        let (name, arguments) = value.call_values().ok_or_else(invalid)?;
+        let texts: Vec<&str> = arguments.iter().map(|value| value.text.as_str()).collect();
+        let number = |text: &str| text.parse::<i32>().map_err(|_| invalid());
+        let transform = match (name.as_str(), texts.as_slice()) {
+            ("CAPTURE", [pattern]) => {
+                // The first group is captured, when there is any
+                let groups = Regex::new(pattern).map_or(1, |regex| regex.captures_len());
+                Transform::Capture(String::from(*pattern), cmp::min(groups - 1, 1))
+            }
+            ("CAPTURE", [pattern, group]) => {
+                let group = group.parse().map_err(|_| invalid())?;
+                Transform::Capture(String::from(*pattern), group)
+            }
+            ("REPLACE", [pattern, replacement]) => {
+                Transform::Replace(String::from(*pattern), String::from(*replacement))
+            }
+            ("SPLIT", [separator]) => Transform::Split(String::from(*separator), 0),
+            ("SPLIT", [separator, n]) => Transform::Split(String::from(*separator), number(n)?),
+            ("NORMALIZE", [form]) => Transform::Normalize(match form.to_uppercase().as_str() {
+                "NFC" => Normalization::Nfc,
+                "NFD" => Normalization::Nfd,
+                "NFKC" => Normalization::Nfkc,
+                "NFKD" => Normalization::Nfkd,
+                _ => return Err(invalid()),
+            }),
+            ("PREFIX", [prefix]) => Transform::Prefix(String::from(*prefix)),
+            ("SUFFIX", [suffix]) => Transform::Suffix(String::from(*suffix)),
+            ("SUBSTRING", [start]) => Transform::Substring(number(start)?, None),
+            ("SUBSTRING", [start, end]) => Transform::Substring(number(start)?, Some(number(end)?)),
+            _ => return Err(invalid()),
         };
+        Ok(transform)
+    }
 
-        Ok(token[starting_bracket_index + 1..closing_bracket_index].to_string())
+    /// An error underlining the whole command (eg. when an argument is missing).
+    fn error(&self, command: &Command, message: &str) -> ParseError {
+        self.error_at(command.span, message)
     }
 
-    fn query_current_step(&self) -> String {
-        self.path_tokens.get(self.current).unwrap().clone()
+    fn error_at(&self, span: Span, message: &str) -> ParseError {
+        ParseError::new(&self.source, span, message)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fetch::MapFetcher;
+    use crate::path::PathFinder;
+    use crate::test_util::TempDir;
+    use scraper::Html;
+    use std::cell::RefCell;
 
     #[test]
     fn test_start_parse() {
         let path = r#"START(SELECTOR: div, SELECT: 0)"#;
         let mut resolver = PathResolver::new(path);
 
-        let path = resolver.resolve();
+        let path = resolver.resolve().unwrap();
 
         assert_eq!(1, path.len());
         matches::assert_matches!(path.get(0).unwrap(), PathStep::Start(_));
     }
 
+    #[test]
+    fn test_start_all_parse() {
+        let path = r#"START(SELECTOR: tr.athing, SELECT: ALL) -> FIND(SELECTOR: a, LOC: TEXT)"#;
+        let mut resolver = PathResolver::new(path);
+
+        let path = resolver.resolve().unwrap();
+
+        match path.get(0).unwrap() {
+            PathStep::Start(d) => matches::assert_matches!(d.1, ElementSelection::All(_)),
+            _ => panic!("Failed"),
+        }
+    }
+
     #[test]
     fn test_missing_selector_start_parse() {
         let path = r#"START(SELECT: 0)"#;
         let mut resolver = PathResolver::new(path);
 
-        let path = resolver.resolve();
+        let errors = resolver.resolve().unwrap_err();
 
-        assert_eq!(2, resolver.errors.len());
-        assert_eq!(0, path.len());
+        assert_eq!(2, errors.len());
     }
 
     #[test]
@@ -468,7 +1336,7 @@ mod tests {
         let path = r#"START(SELECTOR: div)"#;
         let mut resolver = PathResolver::new(path);
 
-        let path = resolver.resolve();
+        let path = resolver.resolve().unwrap();
 
         assert_eq!(1, path.len());
         match path.get(0).unwrap() {
@@ -485,10 +1353,9 @@ mod tests {
         let path = r#"START(SELECTOR: div, SELECT: a32)"#;
         let mut resolver = PathResolver::new(path);
 
-        let path = resolver.resolve();
+        let errors = resolver.resolve().unwrap_err();
 
-        assert_eq!(2, resolver.errors.len());
-        assert_eq!(0, path.len());
+        assert_eq!(2, errors.len());
     }
 
     #[test]
@@ -496,20 +1363,304 @@ mod tests {
         let path = r#"START(SELECTOR: test) -> DESCEND(SELECTOR: div, SELECT: 0)"#;
         let mut resolver = PathResolver::new(path);
 
-        let path = resolver.resolve();
+        let path = resolver.resolve().unwrap();
 
         assert_eq!(2, path.len());
         matches::assert_matches!(path.get(1).unwrap(), PathStep::Descend(_));
     }
 
+    #[test]
+    fn test_foreach_parse() {
+        let path = r#"START(SELECTOR: table) -> FOREACH(SELECTOR: tr.athing) -> FIND(SELECTOR: a, LOC: TEXT)"#;
+        let mut resolver = PathResolver::new(path);
+
+        let path = resolver.resolve().unwrap();
+
+        assert_eq!(3, path.len());
+        matches::assert_matches!(path.get(1).unwrap(), PathStep::ForEach(d) if d.0 == Selector::from("tr.athing"));
+    }
+
+    #[test]
+    fn test_populate_scoped_parse() {
+        let path = r#"START(SELECTOR: div.story) -> POPULATE(NAME: title, SELECTOR: h1, LOC: TEXT)
+            -> DESCEND(SELECTOR: div.comment) -> POPULATE(NAME: comment, SELECTOR: p, LOC: TEXT)"#;
+        let html = Html::parse_fragment(
+            r#"<div class="story"><p>Not a comment</p><h1>Title</h1><div class="comment"><p>Comment</p></div></div>"#,
+        );
+        let html = RefCell::new(&html);
+
+        let path = PathResolver::new(path).resolve().unwrap();
+        let mut path_finder = PathFinder::new(&path, html.borrow());
+        path_finder.search_path().unwrap();
+
+        assert_eq!(4, path.len());
+        matches::assert_matches!(path.get(1).unwrap(), PathStep::Populate(_));
+        assert_eq!("Title", path_finder.map["title"]);
+        assert_eq!("Comment", path_finder.map["comment"]);
+    }
+
+    #[test]
+    fn test_populate_block_parse() {
+        let path = r#"START(SELECTOR: div) -> {
+            POPULATE(NAME: title, SELECTOR: h1, LOC: TEXT),
+            POPULATE(NAME: link, SELECTOR: a, LOC: ATTR(href))
+        }"#;
+        let mut resolver = PathResolver::new(path);
+
+        let path = resolver.resolve().unwrap();
+
+        assert_eq!(2, path.len());
+        match path.get(1).unwrap() {
+            PathStep::Populate(fields) => {
+                assert!(fields.contains_key("title"));
+                assert!(fields.contains_key("link"));
+            }
+            _ => panic!("Failed"),
+        }
+    }
+
+    #[test]
+    fn test_invalid_block_parse() {
+        let path = r#"START(SELECTOR: div) -> { POPULATE(NAME: a, SELECTOR: a, LOC: TEXT), FIND(SELECTOR: b, LOC: TEXT) }"#;
+        let mut resolver = PathResolver::new(path);
+
+        let errors = resolver.resolve().unwrap_err();
+
+        assert_eq!(1, errors.len());
+        assert_eq!("Only POPULATE commands can be grouped", errors[0].message);
+    }
+
+    #[test]
+    fn test_navigation_parse() {
+        let path = r#"START(SELECTOR: th) -> ASCEND() -> CLOSEST(SELECTOR: table) -> NEXT_SIBLING() -> PREV_SIBLING(SELECTOR: p) -> ASCEND(LEVELS: x)"#;
+
+        let errors = parse_path(path).unwrap_err();
+        let path = parse_path(&path.replace("LEVELS: x", "LEVELS: 2")).unwrap();
+
+        assert_eq!(1, errors.len());
+        assert_eq!("Invalid number of levels", errors[0].message);
+        matches::assert_matches!(path.get(1).unwrap(), PathStep::Ascend(1));
+        matches::assert_matches!(path.get(2).unwrap(), PathStep::Closest(s) if *s == Selector::from("table"));
+        matches::assert_matches!(path.get(3).unwrap(), PathStep::NextSibling(s) if *s == Selector::from("*"));
+        matches::assert_matches!(path.get(4).unwrap(), PathStep::PrevSibling(s) if *s == Selector::from("p"));
+        matches::assert_matches!(path.get(5).unwrap(), PathStep::Ascend(2));
+    }
+
+    #[test]
+    fn test_selections_parse() {
+        let path = r#"START(SELECTOR: ul, SELECT: -1) -> DESCEND(SELECTOR: li, SELECT: LAST) -> FIND(SELECTOR: a, SELECT: 1..-1, DELIMITER: ", ", LOC: TEXT) -> FIND(SELECTOR: b, SELECT: -3.., LOC: TEXT) -> FIND(SELECTOR: i, SELECT: EVERY(2, 1), LOC: TEXT)"#;
+
+        let parsed = parse_path(path).unwrap();
+
+        matches::assert_matches!(
+            parsed.get(0).unwrap(),
+            PathStep::Start(Destination(_, ElementSelection::Single(-1), None))
+        );
+        matches::assert_matches!(
+            parsed.get(1).unwrap(),
+            PathStep::Descend(Destination(_, ElementSelection::Last, None))
+        );
+        match parsed.get(2).unwrap() {
+            PathStep::Find(identity) => matches::assert_matches!(
+                &identity.destination.1,
+                ElementSelection::Range(1, Some(-1), d) if d == ", "
+            ),
+            _ => panic!("Failed"),
+        }
+        assert_eq!(path, format_path(&parsed));
+    }
+
+    #[test]
+    fn test_text_filter_parse() {
+        let path = r#"START(SELECTOR: table, SELECT: 0) -> DESCEND(SELECTOR: tr, SELECT: 0, TEXT: CONTAINS(price), IGNORE_CASE: TRUE) -> FIND(SELECTOR: td, SELECT: 0, TEXT: MATCHES(^\d+$), LOC: TEXT) -> FIND(SELECTOR: th, SELECT: 0, TEXT: "Total (EUR)", LOC: TEXT)"#;
+        let invalid = r#"START(SELECTOR: td) -> FIND(SELECTOR: a, TEXT: MATCHES(*x), LOC: TEXT) -> FIND(SELECTOR: a, IGNORE_CASE: TRUE, LOC: TEXT)"#;
+
+        let parsed = parse_path(path).unwrap();
+        let errors = parse_path(invalid).unwrap_err();
+        let json = serde_json::to_string(&parsed).unwrap();
+
+        match parsed.get(1).unwrap() {
+            PathStep::Descend(Destination(_, _, Some(filter))) => {
+                assert_eq!(TextMatch::Contains, filter.mode);
+                assert_eq!("price", filter.text);
+                assert!(filter.ignore_case);
+            }
+            _ => panic!("Failed"),
+        }
+        assert_eq!(path, format_path(&parsed));
+        assert!(json.contains(r#"["table",{"single":0}]"#));
+        assert!(json.contains(r#"{"mode":"regex","text":"^\\d+$","ignore_case":false}"#));
+        assert_eq!(
+            format_path(&parsed),
+            format_path(&serde_json::from_str::<Path>(&json).unwrap())
+        );
+        assert_eq!(2, errors.len());
+        assert_eq!("Invalid regular expression", errors[0].message);
+        assert_eq!("Missing text to match", errors[1].message);
+    }
+
+    #[test]
+    fn test_for_each_filter_parse() {
+        let path = r#"START(SELECTOR: table, SELECT: 0) -> FOREACH(SELECTOR: tr, TEXT: CONTAINS(price), IGNORE_CASE: TRUE) -> FOREACH(SELECTOR: td, SELECT: 1..3)"#;
+
+        let parsed = parse_path(path).unwrap();
+        let json = serde_json::to_string(&parsed).unwrap();
+        let plain: Path = serde_json::from_str(r#"[{"for_each":"li"}]"#).unwrap();
+
+        match parsed.get(1).unwrap() {
+            PathStep::ForEach(Destination(_, ElementSelection::All(_), Some(filter))) => {
+                assert_eq!(TextMatch::Contains, filter.mode);
+                assert_eq!("price", filter.text);
+                assert!(filter.ignore_case);
+            }
+            _ => panic!("Failed"),
+        }
+        assert_eq!(path, format_path(&parsed));
+        assert_eq!(
+            format_path(&parsed),
+            format_path(&serde_json::from_str::<Path>(&json).unwrap())
+        );
+        assert_eq!("FOREACH(SELECTOR: li)", format_path(&plain));
+        assert_eq!(
+            r#"[{"for_each":"li"}]"#,
+            serde_json::to_string(&plain).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_label_parse() {
+        let path = r#"START(SELECTOR: div, SELECT: 0) -> PAIRS(LABEL: dt, VALUE: dd) -> PAIRS(LABEL: b) -> FIND(SELECTOR: th, SELECT: 0, TEXT: Price, LOC: FOLLOWING(td)) -> FIND(SELECTOR: b, SELECT: 0, LOC: FOLLOWING) -> FIND(SELECTOR: dt, SELECT: 0, LOC: FOLLOWING(XPATH(self::dd)))"#;
+
+        let parsed = parse_path(path).unwrap();
+        let errors = parse_path("START(SELECTOR: div) -> PAIRS(VALUE: dd)").unwrap_err();
+
+        matches::assert_matches!(
+            parsed.get(1).unwrap(),
+            PathStep::Pairs(Pairs { value: Some(_), .. })
+        );
+        matches::assert_matches!(
+            parsed.get(2).unwrap(),
+            PathStep::Pairs(Pairs { value: None, .. })
+        );
+        assert_eq!(path, format_path(&parsed));
+        assert_eq!(1, errors.len());
+        assert_eq!("Missing label selector", errors[0].message);
+    }
+
+    #[test]
+    fn test_table_parse() {
+        let path = r#"START(SELECTOR: table, SELECT: 0) -> TABLE(COLUMN: Name, COLUMN: "Price, net", RENAME: Name = title, RENAME: "Price, net = price") -> TABLE()"#;
+
+        let parsed = parse_path(path).unwrap();
+        let errors = parse_path("START(SELECTOR: table) -> TABLE(RENAME: price)").unwrap_err();
+
+        match parsed.get(1).unwrap() {
+            PathStep::Table(table) => {
+                assert_eq!(vec!["Name", "Price, net"], table.columns);
+                assert_eq!("price", table.rename["Price, net"]);
+            }
+            _ => panic!("Failed"),
+        }
+        assert_eq!(path, format_path(&parsed));
+        assert_eq!(1, errors.len());
+        assert_eq!("Expected `header = name`", errors[0].message);
+    }
+
+    #[test]
+    fn test_transforms_parse() {
+        let path = r#"START(SELECTOR: div, SELECT: 0) -> FIND(SELECTOR: span, SELECT: 0, LOC: TEXT, TRANSFORM: COLLAPSE_WHITESPACE, TRANSFORM: CAPTURE("(\\d+),(\\d+)", 2), TRANSFORM: REPLACE(",", ""), TRANSFORM: SPLIT(" ", -1), TRANSFORM: NORMALIZE(NFKC), TRANSFORM: SUBSTRING(1, -1), TRANSFORM: PREFIX("€ "))"#;
+        let invalid = r#"START(SELECTOR: div) -> FIND(SELECTOR: a, LOC: TEXT, TRANSFORM: REVERSE) -> FIND(SELECTOR: a, LOC: TEXT, TRANSFORM: CAPTURE(\d+, x)) -> FIND(SELECTOR: a, LOC: TEXT, TRANSFORM: TRIM, TRANSFORM: REPLACE("(", x))"#;
+
+        let parsed = parse_path(path).unwrap();
+        let errors = parse_path(invalid).unwrap_err();
+        let field = parse_field("FIELD(SELECTOR: b, LOC: TEXT, TRANSFORM: CAPTURE((\\d+) EUR))");
+
+        match parsed.get(1).unwrap() {
+            PathStep::Find(identity) => {
+                assert_eq!(7, identity.transforms.len());
+                assert_eq!(
+                    Transform::Capture(String::from(r"(\d+),(\d+)"), 2),
+                    identity.transforms[1]
+                );
+                assert_eq!(
+                    Transform::Prefix(String::from("€ ")),
+                    identity.transforms[6]
+                );
+            }
+            _ => panic!("Failed"),
+        }
+        assert_eq!(path, format_path(&parsed));
+        assert_eq!(
+            Transform::Capture(String::from(r"(\d+) EUR"), 1),
+            field.unwrap().1.transforms[0]
+        );
+        assert_eq!(3, errors.len());
+        assert_eq!("Invalid transform", errors[0].message);
+        assert_eq!("Invalid transform", errors[1].message);
+        assert_eq!("Invalid regular expression", errors[2].message);
+    }
+
+    #[test]
+    fn test_xpath_selector_parse() {
+        let path = r#"START(SELECTOR: XPATH(//table), SELECT: ALL) -> FIND(SELECTOR: XPATH("td[2]/a"), LOC: ATTR(href)) -> CLOSEST(SELECTOR: XPATH(//div/))"#;
+
+        let errors = parse_path(path).unwrap_err();
+        let path = path.replace(" -> CLOSEST(SELECTOR: XPATH(//div/))", "");
+        let parsed = parse_path(&path).unwrap();
+
+        assert_eq!(1, errors.len());
+        assert_eq!("Invalid XPath", errors[0].message);
+        match parsed.get(1).unwrap() {
+            PathStep::Find(identity) => assert_eq!(
+                Selector::XPath(String::from("td[2]/a")),
+                identity.destination.0
+            ),
+            _ => panic!("Failed"),
+        }
+        assert_eq!(
+            r#"START(SELECTOR: XPATH(//table), SELECT: ALL) -> FIND(SELECTOR: XPATH(td[2]/a), SELECT: 0, LOC: ATTR(href))"#,
+            format_path(&parsed)
+        );
+        assert!(serde_json::to_string(&parsed)
+            .unwrap()
+            .contains(r#"{"xpath":"//table"}"#));
+    }
+
+    #[test]
+    fn test_invalid_selections_parse() {
+        let path = r#"START(SELECTOR: ul) -> DESCEND(SELECTOR: li, SELECT: 1..) -> FIND(SELECTOR: a, SELECT: EVERY(0), LOC: TEXT) -> FIND(SELECTOR: a, SELECT: 1..x, LOC: TEXT)"#;
+
+        let errors = parse_path(path).unwrap_err();
+
+        assert_eq!(3, errors.len());
+        assert_eq!("Only a single element can be selected", errors[0].message);
+        assert_eq!("Invalid select element number", errors[1].message);
+        assert_eq!("Invalid select element number", errors[2].message);
+    }
+
+    #[test]
+    fn test_locations_parse() {
+        let path = "START(SELECTOR: div, SELECT: 0) -> FIND(SELECTOR: a, SELECT: 0, LOC: own_text) -> FIND(SELECTOR: a, SELECT: 0, LOC: INNER_HTML) -> FIND(SELECTOR: a, SELECT: 0, LOC: ATTRIBUTES)";
+
+        let parsed = parse_path(path).unwrap();
+
+        match parsed.get(1).unwrap() {
+            PathStep::Find(identity) => matches::assert_matches!(
+                identity.destination_location,
+                DestinationLocation::OwnText
+            ),
+            _ => panic!("Failed"),
+        }
+        assert_eq!(path.replace("own_text", "OWN_TEXT"), format_path(&parsed));
+    }
+
     #[test]
     fn test_find_parse() {
         let path = r#"START(SELECTOR: test) -> FIND(SELECTOR: div, SELECT: 0, LOC: ATTR(test))"#;
         let mut resolver = PathResolver::new(path);
 
-        let path = resolver.resolve();
+        let path = resolver.resolve().unwrap();
 
-        resolver.errors.iter().for_each(|p| println!("{}", p));
         assert_eq!(2, path.len());
         matches::assert_matches!(path.get(1).unwrap(), PathStep::Find(_));
     }
@@ -519,10 +1670,10 @@ mod tests {
         let path = r#"FIND(SELECTOR: div, SELECT: 0)"#;
         let mut resolver = PathResolver::new(path);
 
-        let path = resolver.resolve();
+        let errors = resolver.resolve().unwrap_err();
 
-        resolver.errors.iter().for_each(|p| println!("{}", p));
-        assert_eq!(2, resolver.errors.len());
+        errors.iter().for_each(|p| println!("{}", p));
+        assert_eq!(2, errors.len());
     }
 
     #[test]
@@ -530,10 +1681,10 @@ mod tests {
         let path = r#"FIND(SELECTOR: div, SELECT: 0, LOC: INVALID)"#;
         let mut resolver = PathResolver::new(path);
 
-        let path = resolver.resolve();
+        let errors = resolver.resolve().unwrap_err();
 
-        resolver.errors.iter().for_each(|p| println!("{}", p));
-        assert_eq!(2, resolver.errors.len());
+        errors.iter().for_each(|p| println!("{}", p));
+        assert_eq!(2, errors.len());
     }
 
     #[test]
@@ -541,10 +1692,10 @@ mod tests {
         let path = r#"NONEXISTINGCOMMAND(NAME: test, SELECTOR: div, SELECT: 0, LOC: TEXT)"#;
         let mut resolver = PathResolver::new(path);
 
-        let path = resolver.resolve();
+        let errors = resolver.resolve().unwrap_err();
 
-        resolver.errors.iter().for_each(|p| println!("{}", p));
-        assert_eq!(2, resolver.errors.len());
+        errors.iter().for_each(|p| println!("{}", p));
+        assert_eq!(2, errors.len());
     }
 
     #[test]
@@ -552,10 +1703,294 @@ mod tests {
         let path = r#"FIND(SELECTOR: div, SELECT: 0, LOC: TEXT)"#;
         let mut resolver = PathResolver::new(path);
 
-        let path = resolver.resolve();
+        let errors = resolver.resolve().unwrap_err();
+
+        errors.iter().for_each(|p| println!("{}", p));
+        assert_eq!(1, errors.len());
+    }
+
+    #[test]
+    fn test_error_spans_parse() {
+        let path = "START(SELECTOR: div)\n-> FIND(SELECTOR: a, SELECT: x1, LOC: NOWHERE)";
+
+        let errors = parse_path(path).unwrap_err();
+
+        assert_eq!(2, errors.len());
+        assert_eq!("Invalid select element number", errors[0].message);
+        assert_eq!((2, 30), (errors[0].line, errors[0].column));
+        assert_eq!("Invalid location", errors[1].message);
+        assert_eq!((2, 39), (errors[1].line, errors[1].column));
+        assert_eq!(
+            "error: Invalid select element number\n --> 2:30\n  |\n\
+             2 | -> FIND(SELECTOR: a, SELECT: x1, LOC: NOWHERE)\n  |                              ^^",
+            errors[0].to_string()
+        );
+    }
+
+    #[test]
+    fn test_selectors_preserved_parse() {
+        let path = r#"START(SELECTOR: div.Title > ul) -> FIND(SELECTOR: a[href^="http:"], SELECT: ALL(, ), LOC: ATTR(HREF))"#;
+        let mut resolver = PathResolver::new(path);
+
+        let path = resolver.resolve().unwrap();
+
+        match path.get(0).unwrap() {
+            PathStep::Start(d) => assert_eq!(Selector::from("div.Title > ul"), d.0),
+            _ => panic!("Failed"),
+        }
+        match path.get(1).unwrap() {
+            PathStep::Find(identity) => {
+                assert_eq!(
+                    Selector::from(r#"a[href^="http:"]"#),
+                    identity.destination.0
+                );
+                matches::assert_matches!(
+                    &identity.destination.1,
+                    ElementSelection::All(d) if d == ", "
+                );
+                matches::assert_matches!(
+                    &identity.destination_location,
+                    DestinationLocation::Attr(a) if a == "href"
+                );
+            }
+            _ => panic!("Failed"),
+        }
+    }
+
+    #[test]
+    fn test_field_parse() {
+        let (name, identity) =
+            parse_field(r#"FIELD(NAME: title, SELECTOR: h1.Title, LOC: TEXT)"#).unwrap();
+
+        assert_eq!(Some(String::from("title")), name);
+        assert_eq!(Selector::from("h1.Title"), identity.destination.0);
+        matches::assert_matches!(identity.destination.1, ElementSelection::Single(0));
+        matches::assert_matches!(identity.destination_location, DestinationLocation::Text);
+    }
+
+    #[test]
+    fn test_invalid_field_parse() {
+        let errors =
+            parse_field(r#"FIELD(SELECTOR: h1, SELECT: x) -> FIND(SELECTOR: a, LOC: TEXT)"#)
+                .unwrap_err();
+
+        assert_eq!(3, errors.len());
+        assert_eq!("Missing location", errors[0].message);
+        assert_eq!("Invalid select element number", errors[1].message);
+        assert_eq!("A field consists of a single command", errors[2].message);
+    }
+
+    #[test]
+    fn test_fields_build() {
+        let config: Config = serde_json::from_str(
+            r#"{"meta": {"populator": "single", "base_url": "http://test"},
+            "paths": [],
+            "fields": {"title": "FIELD(SELECTOR: h1, LOC: TEXT)",
+                       "link": "FIELD(SELECTOR: a, LOC: ATTR(href))"}}"#,
+        )
+        .unwrap();
+        let mut fetcher = MapFetcher::new();
+        fetcher.insert_page(
+            "http://test",
+            r#"<h1>Weather</h1><a href="/today">Today</a>"#,
+        );
+        let mut parser = Parser::new(config);
+        parser.set_fetcher(Arc::new(fetcher));
+
+        let mut populator = parser.build().unwrap();
+        populator.run().unwrap();
+
+        match populator {
+            Populator::Single(spop) => {
+                assert_eq!("Weather", spop.map["title"]);
+                assert_eq!("/today", spop.map["link"]);
+            }
+            _ => panic!("Failed"),
+        }
+    }
+
+    #[test]
+    fn test_field_name_differs_from_key() {
+        let config: Config = serde_json::from_str(
+            r#"{"meta": {"populator": "single", "base_url": "http://test"},
+            "paths": [],
+            "fields": {"title": "FIELD(NAME: heading, SELECTOR: h1, LOC: TEXT)"}}"#,
+        )
+        .unwrap();
+
+        let mut parser = Parser::new(config);
 
-        resolver.errors.iter().for_each(|p| println!("{}", p));
-        assert_eq!(1, resolver.errors.len());
+        assert!(matches::matches!(
+            parser.build(),
+            Err(DebrisError::Config(_))
+        ));
     }
 
+    #[test]
+    fn test_format_path_round_trip() {
+        let path = r#"START(SELECTOR: div.Title > ul, SELECT: ALL) -> FOREACH(SELECTOR: li) -> { POPULATE(NAME: link, SELECTOR: "a, area", SELECT: 0, LOC: ATTR(href)), POPULATE(NAME: title, SELECTOR: a, SELECT: ALL(, ), LOC: TEXT) } -> DESCEND(SELECTOR: p, SELECT: 1) -> FIND(SELECTOR: span, SELECT: 2, LOC: TEXT)"#;
+
+        let formatted = format_path(&parse_path(path).unwrap());
+
+        assert_eq!(path, formatted);
+    }
+
+    #[test]
+    fn test_format_built_path() {
+        let path = PathBuilder::new()
+            .start(Destination::new("ul", ElementSelection::first()))
+            .populate_one(
+                "say \"hi\"",
+                "h1, h2",
+                ElementSelection::All(String::from(")")),
+                DestinationLocation::Text,
+            )
+            .find_all("a", "", DestinationLocation::Attr(String::from("href")))
+            .build();
+
+        let formatted = format_path(&path);
+        let parsed = parse_path(&formatted).unwrap();
+
+        assert_eq!(
+            r#"START(SELECTOR: ul, SELECT: 0) -> POPULATE(NAME: say "hi", SELECTOR: "h1, h2", SELECT: ALL(")"), LOC: TEXT) -> FIND(SELECTOR: a, SELECT: ALL, LOC: ATTR(href))"#,
+            formatted
+        );
+        assert_eq!(formatted, format_path(&parsed));
+    }
+
+    #[test]
+    fn test_structured_paths_config() {
+        let path = PathBuilder::new()
+            .start(Destination::new("ul", ElementSelection::first()))
+            .for_each("li")
+            .populate_one(
+                "title",
+                "a",
+                ElementSelection::first(),
+                DestinationLocation::Text,
+            )
+            .build();
+        let json = format!(
+            r#"{{"meta": {{"populator": "single", "base_url": "http://test"}},
+            "paths": [{}, "START(SELECTOR: ul) -> FIND(SELECTOR: a, LOC: TEXT)"],
+            "fields": {{}}}}"#,
+            serde_json::to_string(&path).unwrap()
+        );
+
+        let config: Config = serde_json::from_str(&json).unwrap();
+
+        matches::assert_matches!(&config.paths[0], PathDefinition::Steps(_));
+        matches::assert_matches!(&config.paths[1], PathDefinition::Text(_));
+        assert_eq!(
+            format_path(&path),
+            format_path(&config.paths[0].resolve().unwrap())
+        );
+        assert_eq!(
+            r#"[{"start":["ul",{"single":0}]},{"for_each":"li"},{"populate":{"title":{"destination":["a",{"single":0}],"destination_location":"text"}}}]"#,
+            serde_json::to_string(&config.paths[0]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid_populator_build() {
+        let config: Config = serde_json::from_str(
+            r#"{"meta": {"populator": "nonexisting", "base_url": "http://test"},
+            "paths": [], "fields": {}}"#,
+        )
+        .unwrap();
+        let mut parser = Parser::new(config);
+
+        let populator = parser.build();
+
+        matches::assert_matches!(populator.err(), Some(DebrisError::Config(_)));
+    }
+
+    #[test]
+    fn test_retry_policy_config() {
+        let config: Config = serde_json::from_str(
+            r#"{"meta": {"populator": "single", "base_url": "http://test",
+            "retry": {"max_attempts": 5, "retry_statuses": [503]}},
+            "paths": [], "fields": {}}"#,
+        )
+        .unwrap();
+
+        let policy = config.meta.retry.unwrap();
+
+        assert_eq!(5, policy.max_attempts);
+        assert_eq!(vec![503], policy.retry_statuses);
+        assert_eq!(RetryPolicy::default().base_delay_ms, policy.base_delay_ms);
+    }
+
+    #[test]
+    fn test_request_settings_config() {
+        let config: Config = serde_json::from_str(
+            r#"{"meta": {"populator": "single", "base_url": "http://test",
+            "user_agent": "Mozilla/5.0", "accept_language": "hu-HU",
+            "headers": {"X-Requested-With": "debris"}, "cookies": {"b": "2", "a": "1"}},
+            "paths": [], "fields": {}}"#,
+        )
+        .unwrap();
+
+        let settings = config.meta.request_settings();
+
+        assert_eq!(Some(String::from("Mozilla/5.0")), settings.user_agent);
+        assert_eq!(Some(String::from("hu-HU")), settings.accept_language);
+        assert_eq!(
+            vec![(String::from("X-Requested-With"), String::from("debris"))],
+            settings.headers
+        );
+        assert_eq!(
+            vec![
+                (String::from("a"), String::from("1")),
+                (String::from("b"), String::from("2"))
+            ],
+            settings.cookies
+        );
+    }
+
+    #[test]
+    fn test_login_is_posted_on_run() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        let config: Config = serde_json::from_str(&format!(
+            r#"{{"meta": {{"populator": "single", "base_url": "http://{0}/page",
+            "session": {{"login": {{"url": "http://{0}/login"}}}}}},
+            "paths": [], "fields": {{}}}}"#,
+            address
+        ))
+        .unwrap();
+        let mut parser = Parser::new(config);
+
+        let mut populator = parser.build().unwrap();
+
+        matches::assert_matches!(populator.run(), Err(DebrisError::Fetch { io: Some(_), .. }));
+    }
+
+    #[test]
+    fn test_local_source_build() {
+        let dir = TempDir::new("local");
+        std::fs::write(dir.join("first.html"), r#"<ul id="results"><a>first</a></ul>"#).unwrap();
+        std::fs::write(dir.join("second.html"), r#"<ul id="results"><a>second</a></ul>"#).unwrap();
+        let config: Config = serde_json::from_str(&format!(
+            r#"{{"meta": {{"populator": "single", "local": "{}"}},
+            "paths": ["START(SELECTOR: ul) -> FIND(SELECTOR: a, SELECT: 0, LOC: TEXT)"],
+            "fields": {{}}}}"#,
+            dir.path().to_str().unwrap()
+        ))
+        .unwrap();
+        let mut parser = Parser::new(config);
+
+        let mut populator = parser.build().unwrap();
+        populator.run().unwrap();
+
+        match populator {
+            Populator::Local(spops) => {
+                assert_eq!(2, spops.len());
+                assert_eq!(vec!["first"], spops[0].values);
+                assert_eq!(vec!["second"], spops[1].values);
+            }
+            _ => panic!("Failed"),
+        }
+    }
 }
@@ -1,32 +1,106 @@
-use crate::field::concatenate_all;
-use crate::field::find_all;
-use crate::field::find_single;
+use crate::error::DebrisError;
+use crate::error::Result;
+use crate::field::find_selected;
+use crate::field::find_value;
+use crate::field::parse_selector;
 use crate::field::Destination;
 use crate::field::DestinationLocation;
 use crate::field::ElementSelection;
 use crate::field::FieldIdentity;
+use crate::field::Selector;
+use crate::label::Pairs;
+use crate::table::Table;
 use scraper::ElementRef;
 use scraper::Html;
-use scraper::Selector;
+use serde::{Deserialize, Serialize};
 use std::cell::Ref;
 use std::collections::HashMap;
+use std::iter;
+use std::mem;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 /// Building blocks of a path.
 pub enum PathStep {
     /// Lower the hierarchy level by 1.
     Descend(Destination),
-    /// Every path should be started with this.
+    /// Every path should be started with this. Selecting all the elements runs the rest of the
+    /// path on every match, each populating its own record.
     Start(Destination),
     /// Populate fields on current level.
     Populate(HashMap<String, FieldIdentity>),
     /// Find values on current level without field names.
     Find(FieldIdentity),
+    /// Run the rest of the path on every element of the destination (every matched element
+    /// without a selection), each populating its own record.
+    #[serde(with = "each_destination")]
+    ForEach(Destination),
+    /// Raise the hierarchy level by the number of levels.
+    Ascend(usize),
+    /// Move to the nearest ancestor (or the element itself) matching the selector.
+    Closest(Selector),
+    /// Move to the nearest following sibling matching the selector.
+    NextSibling(Selector),
+    /// Move to the nearest preceding sibling matching the selector.
+    PrevSibling(Selector),
+    /// Populate a field for every label on current level with the value of the label.
+    Pairs(Pairs),
+    /// Emit a record for every row of the table on current level, starting with the fields
+    /// populated so far.
+    Table(Table),
+}
+
+/// The destination of every element matching the selector.
+fn every(selector: Selector) -> Destination {
+    Destination(selector, ElementSelection::All(String::new()), None)
+}
+
+/// The destination of a `ForEach` step is serialized as its selector, when it keeps every matched
+/// element.
+mod each_destination {
+    use super::every;
+    use crate::field::Destination;
+    use crate::field::ElementSelection;
+    use crate::field::Selector;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum EachForm {
+        Every(Selector),
+        Narrowed(Destination),
+    }
+
+    pub fn serialize<S: Serializer>(
+        destination: &Destination,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let form = match destination {
+            Destination(selector, ElementSelection::All(delimiter), None)
+                if delimiter.is_empty() =>
+            {
+                EachForm::Every(selector.clone())
+            }
+            _ => EachForm::Narrowed(destination.clone()),
+        };
+        form.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Destination, D::Error> {
+        Ok(match EachForm::deserialize(deserializer)? {
+            EachForm::Every(selector) => every(selector),
+            EachForm::Narrowed(destination) => destination,
+        })
+    }
 }
 
 pub struct PathFinder<'a, 'b> {
     html: Ref<'a, &'b Html>,
     pub map: HashMap<String, String>,
+    /// One map for every root, when the path starts on all the matched elements.
+    pub records: Vec<HashMap<String, String>>,
     pub values: Vec<String>,
     path: &'a Vec<PathStep>,
 }
@@ -34,96 +108,158 @@ pub struct PathFinder<'a, 'b> {
 impl<'a, 'b> PathFinder<'a, 'b> {
     pub fn new(path: &'a Vec<PathStep>, html: Ref<'a, &'b Html>) -> PathFinder<'a, 'b> {
         let map = HashMap::<String, String>::new();
+        let records = Vec::<HashMap<String, String>>::new();
         let values = Vec::<String>::new();
         PathFinder {
             html,
             map,
+            records,
             values,
             path,
         }
     }
 
-    pub fn search_path(&mut self) {
+    pub fn search_path(&mut self) -> Result<()> {
         let start = match &self.path.get(0) {
-            Some(start) => {
-                if let PathStep::Start(first) = start {
-                    first
-                } else {
-                    panic!("First element of path should be a Start!");
-                }
+            Some(PathStep::Start(first)) => first,
+            _ => {
+                return Err(DebrisError::Path(String::from(
+                    "First element of path should be a Start!",
+                )));
             }
-            _ => panic!("First element of path should be a Start!"),
         };
 
-        let n = match start.1 {
-            ElementSelection::Single(n) => n,
-            _ => panic!("Can not descend on all element"),
-        };
-        let parsed = Selector::parse(r#"ul[id="results"]"#).unwrap();
-        let selected = match self.html.select(&parsed).nth(n as usize) {
-            Some(s) => s,
-            None => return,
+        let html: &'b Html = *self.html;
+        let matches = match &start.0 {
+            // The root element of the document might be a start as well
+            Selector::Css(css) => html.select(&parse_selector(css)?).collect(),
+            xpath => xpath.select(&html.root_element())?,
         };
+        let roots = start.narrow(matches)?;
+        if start.1.is_multiple() {
+            return self.iterate(roots, 1);
+        }
+        match roots.first() {
+            Some(selected) => self.resolve_path(selected, 1),
+            None => Ok(()),
+        }
+    }
 
-        self.resolve_path(&selected, 1);
+    /// Runs the steps from the level on every element. Each element populates its own record
+    /// starting with the fields populated so far, unless a nested iteration emits the records. An
+    /// element populating no field (eg. a row not matching the rest of the path) is skipped.
+    fn iterate(&mut self, elements: Vec<ElementRef<'b>>, level: usize) -> Result<()> {
+        let inherited = self.map.clone();
+        for element in elements {
+            let emitted = self.records.len();
+            self.resolve_path(&element, level)?;
+            let record = mem::replace(&mut self.map, inherited.clone());
+            if self.records.len() == emitted && record != inherited {
+                self.records.push(record);
+            }
+        }
+        Ok(())
+    }
+
+    /// The rest of the path continues on the element reached by a navigation step, if there is any.
+    fn continue_on(&mut self, element: Option<ElementRef<'b>>, level: usize) -> Result<()> {
+        match element {
+            Some(element) => self.resolve_path(&element, level + 1),
+            None => Ok(()),
+        }
     }
 
-    fn resolve_path(&mut self, element: &ElementRef, level: usize) {
+    fn resolve_path(&mut self, element: &ElementRef<'b>, level: usize) -> Result<()> {
         if level == self.path.len() {
-            return;
+            return Ok(());
         }
 
         match self.path.get(level) {
             Some(step) => match step {
                 PathStep::Descend(destination) => {
-                    let selection = Selector::parse(&destination.0).unwrap();
-                    let n = match destination.1 {
-                        ElementSelection::Single(n) => n,
-                        _ => panic!("Can not descend on all element"),
+                    if destination.1.is_multiple() {
+                        return Err(DebrisError::Path(String::from(
+                            "Can not descend on several elements",
+                        )));
+                    }
+                    let child_element = match destination.select(element)?.first() {
+                        Some(e) => *e,
+                        None => return Ok(()),
                     };
 
-                    let child_element = match element.select(&selection).nth(n as usize) {
-                        Some(e) => e,
-                        None => return,
-                    };
+                    // The rest of the path continues on the child
+                    return self.resolve_path(&child_element, level + 1);
+                }
+
+                PathStep::ForEach(destination) => {
+                    let elements = destination.select(element)?;
+                    return self.iterate(elements, level + 1);
+                }
+
+                PathStep::Ascend(levels) => {
+                    let ancestors = element.ancestors().filter_map(ElementRef::wrap);
+                    let ancestor = iter::once(*element).chain(ancestors).nth(*levels);
+                    return self.continue_on(ancestor, level);
+                }
+
+                PathStep::Closest(selector) => {
+                    let ancestors = element.ancestors().filter_map(ElementRef::wrap);
+                    let candidates = iter::once(*element).chain(ancestors);
+                    let closest = selector.first_match(candidates)?;
+                    return self.continue_on(closest, level);
+                }
+
+                PathStep::NextSibling(selector) => {
+                    let candidates = element.next_siblings().filter_map(ElementRef::wrap);
+                    let sibling = selector.first_match(candidates)?;
+                    return self.continue_on(sibling, level);
+                }
 
-                    self.resolve_path(&child_element, level + 1);
+                PathStep::PrevSibling(selector) => {
+                    let candidates = element.prev_siblings().filter_map(ElementRef::wrap);
+                    let sibling = selector.first_match(candidates)?;
+                    return self.continue_on(sibling, level);
                 }
 
                 PathStep::Populate(field_map) => {
                     for (field_name, identifier) in field_map {
-                        match &identifier.destination.1 {
-                            ElementSelection::Single(n) => {
-                                self.map.insert(
-                                    field_name.clone(),
-                                    find_single(element, identifier, *n as usize),
-                                );
-                            }
-                            ElementSelection::All(delimiter) => {
-                                self.map.insert(
-                                    field_name.clone(),
-                                    concatenate_all(element, identifier, delimiter),
-                                );
-                            }
-                        }
+                        self.map
+                            .insert(field_name.clone(), find_value(element, identifier)?);
                     }
                 }
 
-                PathStep::Find(field_identifier) => match &field_identifier.destination.1 {
-                    ElementSelection::Single(n) => {
-                        self.values
-                            .push(find_single(element, field_identifier, *n as usize));
+                PathStep::Pairs(pairs) => {
+                    for (label, value) in pairs.extract(element)? {
+                        self.map.insert(label, value);
+                    }
+                }
+
+                PathStep::Table(table) => {
+                    for row in table.extract(element) {
+                        let mut record = self.map.clone();
+                        record.extend(row);
+                        self.records.push(record);
                     }
-                    ElementSelection::All(_) => {
-                        self.values.extend(find_all(element, field_identifier));
+                }
+
+                PathStep::Find(field_identifier) => {
+                    if field_identifier.destination.1.is_multiple() {
+                        self.values
+                            .extend(find_selected(element, field_identifier)?);
+                    } else {
+                        self.values.push(find_value(element, field_identifier)?);
                     }
-                },
-                _ => panic!("Invalid path!"),
+                }
+                _ => {
+                    return Err(DebrisError::Path(String::from(
+                        "Start is only allowed as the first element",
+                    )));
+                }
             },
             None => (),
         };
 
-        self.resolve_path(element, level + 1);
+        self.resolve_path(element, level + 1)
     }
 }
 
@@ -151,6 +287,67 @@ impl<'a> PathBuilder {
         return self;
     }
 
+    /// Descend to a single element picked by the selection (eg. the last one).
+    pub fn descend_to<S: Into<Selector>>(
+        &mut self,
+        selector: S,
+        selection: ElementSelection,
+    ) -> &mut Self {
+        self.path.push(PathStep::Descend(Destination(
+            selector.into(),
+            selection,
+            None,
+        )));
+        return self;
+    }
+
+    /// Descend to the single element of the destination (eg. filtered by its text).
+    pub fn descend_into(&mut self, destination: Destination) -> &mut Self {
+        self.path.push(PathStep::Descend(destination));
+        return self;
+    }
+
+    pub fn for_each<S: Into<Selector>>(&mut self, selector: S) -> &mut Self {
+        self.path.push(PathStep::ForEach(every(selector.into())));
+        return self;
+    }
+
+    /// Iterate the elements of the destination (eg. filtered by their text).
+    pub fn for_each_of(&mut self, destination: Destination) -> &mut Self {
+        self.path.push(PathStep::ForEach(destination));
+        return self;
+    }
+
+    pub fn ascend(&mut self, levels: usize) -> &mut Self {
+        self.path.push(PathStep::Ascend(levels));
+        return self;
+    }
+
+    pub fn closest<S: Into<Selector>>(&mut self, selector: S) -> &mut Self {
+        self.path.push(PathStep::Closest(selector.into()));
+        return self;
+    }
+
+    pub fn next_sibling<S: Into<Selector>>(&mut self, selector: S) -> &mut Self {
+        self.path.push(PathStep::NextSibling(selector.into()));
+        return self;
+    }
+
+    pub fn prev_sibling<S: Into<Selector>>(&mut self, selector: S) -> &mut Self {
+        self.path.push(PathStep::PrevSibling(selector.into()));
+        return self;
+    }
+
+    pub fn pairs(&mut self, pairs: Pairs) -> &mut Self {
+        self.path.push(PathStep::Pairs(pairs));
+        return self;
+    }
+
+    pub fn table(&mut self, table: Table) -> &mut Self {
+        self.path.push(PathStep::Table(table));
+        return self;
+    }
+
     pub fn populate(&mut self, population_map: HashMap<String, FieldIdentity>) -> &mut Self {
         self.path.push(PathStep::Populate(population_map));
         return self;
@@ -166,10 +363,7 @@ impl<'a> PathBuilder {
         let mut population = HashMap::new();
         population.insert(
             String::from(field_name),
-            FieldIdentity {
-                destination: Destination::new(selector, selection),
-                destination_location: location,
-            },
+            FieldIdentity::new(Destination::new(selector, selection), location),
         );
         self.path.push(PathStep::Populate(population));
         return self;
@@ -181,28 +375,49 @@ impl<'a> PathBuilder {
         number_of_element: i32,
         location: DestinationLocation,
     ) -> &mut Self {
-        self.path.push(PathStep::Find(FieldIdentity {
-            destination: Destination(
-                String::from(selector),
+        self.path.push(PathStep::Find(FieldIdentity::new(
+            Destination(
+                Selector::from(selector),
                 ElementSelection::Single(number_of_element),
+                None,
             ),
-            destination_location: location,
-        }));
+            location,
+        )));
         return self;
     }
 
     pub fn find_all(
         &mut self,
         selector: &'a str,
+        delimiter: &'a str,
         location: DestinationLocation,
     ) -> &mut Self {
-        self.path.push(PathStep::Find(FieldIdentity {
-            destination: Destination(
-                String::from(selector),
-                ElementSelection::All(String::from("")),
+        self.path.push(PathStep::Find(FieldIdentity::new(
+            Destination(
+                Selector::from(selector),
+                ElementSelection::All(String::from(delimiter)),
+                None,
             ),
-            destination_location: location,
-        }));
+            location,
+        )));
+        return self;
+    }
+
+    pub fn find<S: Into<Selector>>(
+        &mut self,
+        selector: S,
+        selection: ElementSelection,
+        location: DestinationLocation,
+    ) -> &mut Self {
+        self.path.push(PathStep::Find(FieldIdentity::new(
+            Destination(selector.into(), selection, None),
+            location,
+        )));
+        return self;
+    }
+
+    pub fn find_identity(&mut self, identity: FieldIdentity) -> &mut Self {
+        self.path.push(PathStep::Find(identity));
         return self;
     }
 
@@ -215,6 +430,7 @@ impl<'a> PathBuilder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::field::{TextFilter, TextMatch};
     use std::cell::RefCell;
 
     #[test]
@@ -228,7 +444,7 @@ mod tests {
             .build();
         let mut path_finder = PathFinder::new(&path, html.borrow());
 
-        path_finder.search_path();
+        path_finder.search_path().unwrap();
 
         assert_eq!(path_finder.values.get(0).unwrap(), "find me");
     }
@@ -245,7 +461,7 @@ mod tests {
             .build();
         let mut path_finder = PathFinder::new(&path, html.borrow());
 
-        path_finder.search_path();
+        path_finder.search_path().unwrap();
 
         assert_eq!(path_finder.values.get(0).unwrap(), "find me");
     }
@@ -263,7 +479,7 @@ mod tests {
             .build();
         let mut path_finder = PathFinder::new(&path, html.borrow());
 
-        path_finder.search_path();
+        path_finder.search_path().unwrap();
 
         assert_eq!(path_finder.values.get(0).unwrap(), "find me");
         assert_eq!(path_finder.values.get(1).unwrap(), "as well");
@@ -278,23 +494,17 @@ mod tests {
         let mut population = HashMap::new();
         population.insert(
             String::from("first"),
-            FieldIdentity {
-                destination: Destination::new(
-                    r#"span[itemprop="first"]"#,
-                    ElementSelection::first(),
-                ),
-                destination_location: DestinationLocation::Text,
-            },
+            FieldIdentity::new(
+                Destination::new(r#"span[itemprop="first"]"#, ElementSelection::first()),
+                DestinationLocation::Text,
+            ),
         );
         population.insert(
             String::from("second"),
-            FieldIdentity {
-                destination: Destination::new(
-                    r#"span[itemprop="second"]"#,
-                    ElementSelection::first(),
-                ),
-                destination_location: DestinationLocation::Text,
-            },
+            FieldIdentity::new(
+                Destination::new(r#"span[itemprop="second"]"#, ElementSelection::first()),
+                DestinationLocation::Text,
+            ),
         );
         let path = PathBuilder::new()
             .start(Destination::new(
@@ -305,7 +515,7 @@ mod tests {
             .build();
         let mut path_finder = PathFinder::new(&path, html.borrow());
 
-        path_finder.search_path();
+        path_finder.search_path().unwrap();
 
         assert_eq!(path_finder.map.get("first").unwrap(), "find me");
         assert_eq!(path_finder.map.get("second").unwrap(), "as well");
@@ -323,7 +533,7 @@ mod tests {
             .build();
         let mut path_finder = PathFinder::new(&path, html.borrow());
 
-        path_finder.search_path();
+        path_finder.search_path().unwrap();
 
         assert_eq!(path_finder.values.get(0).unwrap(), "find me");
         assert_eq!(path_finder.values.get(1).unwrap(), "as well");
@@ -339,33 +549,24 @@ mod tests {
         let mut second_population = HashMap::new();
         population.insert(
             String::from("first"),
-            FieldIdentity {
-                destination: Destination::new(
-                    r#"span[itemprop="first"]"#,
-                    ElementSelection::first(),
-                ),
-                destination_location: DestinationLocation::Text,
-            },
+            FieldIdentity::new(
+                Destination::new(r#"span[itemprop="first"]"#, ElementSelection::first()),
+                DestinationLocation::Text,
+            ),
         );
         population.insert(
             String::from("second"),
-            FieldIdentity {
-                destination: Destination::new(
-                    r#"span[itemprop="second"]"#,
-                    ElementSelection::first(),
-                ),
-                destination_location: DestinationLocation::Text,
-            },
+            FieldIdentity::new(
+                Destination::new(r#"span[itemprop="second"]"#, ElementSelection::first()),
+                DestinationLocation::Text,
+            ),
         );
         second_population.insert(
             String::from("third"),
-            FieldIdentity {
-                destination: Destination::new(
-                    r#"span[itemprop="first"]"#,
-                    ElementSelection::first(),
-                ),
-                destination_location: DestinationLocation::Text,
-            },
+            FieldIdentity::new(
+                Destination::new(r#"span[itemprop="first"]"#, ElementSelection::first()),
+                DestinationLocation::Text,
+            ),
         );
         let path = PathBuilder::new()
             .start(Destination::new(
@@ -377,7 +578,7 @@ mod tests {
             .build();
         let mut path_finder = PathFinder::new(&path, html.borrow());
 
-        path_finder.search_path();
+        path_finder.search_path().unwrap();
 
         assert_eq!(path_finder.map.get("first").unwrap(), "find me");
         assert_eq!(path_finder.map.get("second").unwrap(), "as well");
@@ -405,9 +606,281 @@ mod tests {
             .build();
         let mut path_finder = PathFinder::new(&path, html.borrow());
 
-        path_finder.search_path();
+        path_finder.search_path().unwrap();
 
         assert_eq!(path_finder.map.get("first").unwrap(), "find me");
     }
 
+    #[test]
+    fn test_start_selector_by_path() {
+        let html_string = r#"<div><p>NOT THIS</p></div><section><p>find me</p></section>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let path = PathBuilder::new()
+            .start(Destination::new("section", ElementSelection::first()))
+            .find_one("p", 0, DestinationLocation::Text)
+            .build();
+        let mut path_finder = PathFinder::new(&path, html.borrow());
+
+        path_finder.search_path().unwrap();
+
+        assert_eq!(vec!["find me"], path_finder.values);
+    }
+
+    #[test]
+    fn test_start_on_all_elements_by_path() {
+        let html_string = r#"<ul><li><a>first</a><i>1</i></li><li><a>second</a><i>2</i></li></ul>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let path = PathBuilder::new()
+            .start(Destination::new("li", ElementSelection::All(String::new())))
+            .populate_one(
+                "title",
+                "a",
+                ElementSelection::first(),
+                DestinationLocation::Text,
+            )
+            .populate_one(
+                "rank",
+                "i",
+                ElementSelection::first(),
+                DestinationLocation::Text,
+            )
+            .build();
+        let mut path_finder = PathFinder::new(&path, html.borrow());
+
+        path_finder.search_path().unwrap();
+
+        assert!(path_finder.map.is_empty());
+        assert_eq!(2, path_finder.records.len());
+        assert_eq!("first", path_finder.records[0]["title"]);
+        assert_eq!("1", path_finder.records[0]["rank"]);
+        assert_eq!("second", path_finder.records[1]["title"]);
+        assert_eq!("2", path_finder.records[1]["rank"]);
+    }
+
+    #[test]
+    fn test_for_each_by_path() {
+        let html_string = r#"<table><caption>Front page</caption>
+        <tr class="athing"><td><a>first</a></td></tr><tr class="spacer"></tr>
+        <tr class="athing"><td><a>second</a></td></tr></table>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let path = PathBuilder::new()
+            .start(Destination::new("table", ElementSelection::first()))
+            .populate_one(
+                "page",
+                "caption",
+                ElementSelection::first(),
+                DestinationLocation::Text,
+            )
+            .for_each("tr.athing")
+            .populate_one(
+                "title",
+                "a",
+                ElementSelection::first(),
+                DestinationLocation::Text,
+            )
+            .build();
+        let mut path_finder = PathFinder::new(&path, html.borrow());
+
+        path_finder.search_path().unwrap();
+
+        assert_eq!(2, path_finder.records.len());
+        assert_eq!("first", path_finder.records[0]["title"]);
+        assert_eq!("Front page", path_finder.records[0]["page"]);
+        assert_eq!("second", path_finder.records[1]["title"]);
+        assert_eq!("Front page", path_finder.records[1]["page"]);
+        assert!(!path_finder.map.contains_key("title"));
+    }
+
+    #[test]
+    fn test_for_each_skips_rows_without_fields_by_path() {
+        let html_string = r#"<table><caption>Front page</caption>
+        <tr><td class="title"><a>first</a></td></tr><tr class="spacer"><td></td></tr>
+        <tr><td class="title"><a>second</a></td></tr></table>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let path = PathBuilder::new()
+            .start(Destination::new("table", ElementSelection::first()))
+            .populate_one(
+                "page",
+                "caption",
+                ElementSelection::first(),
+                DestinationLocation::Text,
+            )
+            .for_each("tr")
+            .descend("td.title", 0)
+            .populate_one(
+                "title",
+                "a",
+                ElementSelection::first(),
+                DestinationLocation::Text,
+            )
+            .build();
+        let mut path_finder = PathFinder::new(&path, html.borrow());
+
+        path_finder.search_path().unwrap();
+
+        assert_eq!(2, path_finder.records.len());
+        assert_eq!("first", path_finder.records[0]["title"]);
+        assert_eq!("second", path_finder.records[1]["title"]);
+    }
+
+    #[test]
+    fn test_ascend_and_closest_by_path() {
+        let html_string = r#"<table><tr><th>Price</th><td><b>12 EUR</b></td></tr></table>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let path = PathBuilder::new()
+            .start(Destination::new("b", ElementSelection::first()))
+            .ascend(1)
+            .find_one("b", 0, DestinationLocation::Text)
+            .closest("tr")
+            .find_one("th", 0, DestinationLocation::Text)
+            .build();
+        let mut path_finder = PathFinder::new(&path, html.borrow());
+
+        path_finder.search_path().unwrap();
+
+        assert_eq!(vec!["12 EUR", "Price"], path_finder.values);
+    }
+
+    #[test]
+    fn test_siblings_by_path() {
+        let html_string =
+            r#"<dl><dt>Director</dt><dd><i>Someone</i></dd><dt>Year</dt><dd><i>1999</i></dd></dl>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let path = PathBuilder::new()
+            .start(Destination::new("dt", ElementSelection::Single(1)))
+            .next_sibling("dd")
+            .populate_one(
+                "year",
+                "*",
+                ElementSelection::first(),
+                DestinationLocation::Text,
+            )
+            .prev_sibling("dd")
+            .populate_one(
+                "director",
+                "*",
+                ElementSelection::first(),
+                DestinationLocation::Text,
+            )
+            .next_sibling("table")
+            .populate_one(
+                "missing",
+                "*",
+                ElementSelection::first(),
+                DestinationLocation::Text,
+            )
+            .build();
+        let mut path_finder = PathFinder::new(&path, html.borrow());
+
+        path_finder.search_path().unwrap();
+
+        assert_eq!("1999", path_finder.map["year"]);
+        assert_eq!("Someone", path_finder.map["director"]);
+        assert!(!path_finder.map.contains_key("missing"));
+    }
+
+    #[test]
+    fn test_xpath_siblings_by_path() {
+        let html_string = r#"<table><tr><th>Price</th><td><b>10</b></td><td class="gross"><b>12</b></td></tr></table>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let path = PathBuilder::new()
+            .start(Destination::new("th", ElementSelection::first()))
+            .next_sibling(Selector::XPath(String::from("td[@class='gross']")))
+            .find_one("b", 0, DestinationLocation::Text)
+            .closest(Selector::XPath(String::from("tr")))
+            .find_one("th", 0, DestinationLocation::Text)
+            .build();
+        let mut path_finder = PathFinder::new(&path, html.borrow());
+
+        path_finder.search_path().unwrap();
+
+        assert_eq!(vec!["12", "Price"], path_finder.values);
+    }
+
+    #[test]
+    fn test_descend_by_text() {
+        let html_string = r#"<table><tr><th>Name</th><td>Lamp</td></tr>
+            <tr><th>Price</th><td>12 EUR</td></tr></table>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let price = TextFilter::new(TextMatch::Contains, "Price");
+        let path = PathBuilder::new()
+            .start(Destination::new("table", ElementSelection::first()))
+            .descend_into(Destination::new("tr", ElementSelection::first()).with_text(price))
+            .find_one("td", 0, DestinationLocation::Text)
+            .build();
+        let mut path_finder = PathFinder::new(&path, html.borrow());
+
+        path_finder.search_path().unwrap();
+
+        assert_eq!(vec!["12 EUR"], path_finder.values);
+    }
+
+    #[test]
+    fn test_pairs_by_path() {
+        let html_string = r#"<table class="details"><tr><th>Director</th><td>Someone</td></tr>
+            <tr><th>Year:</th><td>1999</td></tr></table>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let path = PathBuilder::new()
+            .start(Destination::new("table.details", ElementSelection::first()))
+            .pairs(Pairs::siblings("th", "td"))
+            .build();
+        let mut path_finder = PathFinder::new(&path, html.borrow());
+
+        path_finder.search_path().unwrap();
+
+        assert_eq!(2, path_finder.map.len());
+        assert_eq!("Someone", path_finder.map["Director"]);
+        assert_eq!("1999", path_finder.map["Year"]);
+    }
+
+    #[test]
+    fn test_table_records_by_path() {
+        let html_string = r#"<h1><span>Prices</span></h1><table><tr><th>Name</th><th>Price</th></tr>
+            <tr><td>Lamp</td><td>12</td></tr><tr><td>Chair</td><td>20</td></tr></table>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let path = PathBuilder::new()
+            .start(Destination::new("h1", ElementSelection::first()))
+            .populate_one(
+                "title",
+                "*",
+                ElementSelection::first(),
+                DestinationLocation::Text,
+            )
+            .next_sibling("table")
+            .table(Table::new().rename("Price", "price").build())
+            .build();
+        let mut path_finder = PathFinder::new(&path, html.borrow());
+
+        path_finder.search_path().unwrap();
+
+        assert_eq!(2, path_finder.records.len());
+        assert_eq!("Prices", path_finder.records[0]["title"]);
+        assert_eq!("Chair", path_finder.records[1]["Name"]);
+        assert_eq!("20", path_finder.records[1]["price"]);
+    }
+
+    #[test]
+    fn test_error_on_path_without_start() {
+        let html_string = r#"<div><p>find me</p></div>"#;
+        let html = Html::parse_fragment(&html_string);
+        let html = RefCell::new(&html);
+        let path = PathBuilder::new()
+            .find_one("p", 0, DestinationLocation::Text)
+            .build();
+        let mut path_finder = PathFinder::new(&path, html.borrow());
+
+        let result = path_finder.search_path();
+
+        matches::assert_matches!(result, Err(DebrisError::Path(_)));
+    }
 }
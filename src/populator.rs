@@ -1,36 +1,59 @@
 //! This module provides the main logic of the library
 //!
+use crate::error::DebrisError;
+use crate::error::ErrorKind;
+use crate::error::Result;
+use crate::fetch::Fetcher;
+use crate::fetch::HttpFetcher;
 use crate::field::Destination;
 use crate::field::DestinationLocation;
 use crate::field::ElementSelection;
 use crate::field::FieldIdentity;
 use crate::field::FieldPopulator;
+use crate::field::Selector;
 use crate::path::PathFinder;
 use crate::path::PathStep;
-use reqwest;
+use crate::robots::Robots;
+use crate::robots::DEFAULT_USER_AGENT;
+use crate::throttle::RateLimit;
+use crate::throttle::ThrottledFetcher;
+use crate::xpath::DocumentScope;
 use scraper::Html;
+use std::borrow::Cow;
 use std::cell::RefCell;
+use std::cmp;
 use std::collections::HashMap;
+use std::panic;
+use std::panic::AssertUnwindSafe;
 use std::prelude::v1::Vec;
+use std::result;
 use std::string::ToString;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
 use threadpool::ThreadPool;
 
-pub type Fields<'a> = HashMap<&'a str, FieldIdentity>;
+/// The number of threads crawling the links without a configured maximum or rate limit.
+pub const DEFAULT_MAX_THREADS: usize = 4;
+
+pub type Fields<'a> = HashMap<Cow<'a, str>, FieldIdentity>;
 pub type Path = Vec<PathStep>;
 pub type Paths = Vec<Path>;
 
+#[derive(Clone)]
 pub enum Paging {
     Disabled,
     Enabled(PagingOptions),
 }
 
+#[derive(Clone)]
 pub struct PagingOptions {
     pub extension: String,
     pub range: PagingRange,
 }
 
+#[derive(Clone)]
 pub enum PagingRange {
     Indefinite,
     Page(i32),
@@ -39,31 +62,47 @@ pub enum PagingRange {
 pub struct SinglePopulator<'a> {
     url: String,
     search_detail: SearchDetail<'a>,
+    fetcher: Arc<dyn Fetcher>,
     /// A map, that contains populated field_names
     pub map: HashMap<String, String>,
+    /// Records of the paths starting on all the matched elements, one for every element.
+    pub records: Vec<HashMap<String, String>>,
     /// Values, that are populated without specifying the field_name (eg. extracting links etc..)
     pub values: Vec<String>,
 }
 
 impl<'a> SinglePopulator<'a> {
     pub fn new(url:&'a str, search: SearchDetail<'a>) -> SinglePopulator<'a> {
+        Self::new_with_fetcher(url, search, Arc::new(HttpFetcher::new()))
+    }
+
+    /// Creates a populator, that retrieves the HTML document through the given fetcher.
+    pub fn new_with_fetcher(
+        url: &str,
+        search: SearchDetail<'a>,
+        fetcher: Arc<dyn Fetcher>,
+    ) -> SinglePopulator<'a> {
         let map = HashMap::new();
+        let records = Vec::new();
         let values = Vec::new();
         SinglePopulator {
             url: String::from(url),
             search_detail: search,
+            fetcher,
             map,
+            records,
             values,
         }
     }
 
-    pub fn populate(&mut self) {
-        let html = get_html(&self.url);
+    pub fn populate(&mut self) -> Result<()> {
+        let html = get_html(self.fetcher.as_ref(), &self.url)?;
+        let _scope = DocumentScope::new(&html);
         let html = RefCell::new(&html);
 
         for (field_name, field) in &self.search_detail.fields {
             let mut populator = FieldPopulator::new(html.borrow(), &field);
-            populator.find_field();
+            populator.find_field()?;
             self.map.insert(
                 field_name.to_string(),
                 populator.value.unwrap_or_else(|| "".to_string()),
@@ -72,22 +111,67 @@ impl<'a> SinglePopulator<'a> {
 
         for path in &self.search_detail.paths {
             let mut path_finder = PathFinder::new(&path, html.borrow());
-            path_finder.search_path();
+            path_finder.search_path()?;
             for (k, v) in path_finder.map {
                 self.map.insert(k, v);
             }
+            self.records.extend(path_finder.records);
             self.values.extend(path_finder.values);
         }
+        Ok(())
+    }
+}
+
+type LinkOutcome = result::Result<PopulatedLink, LinkFailure>;
+type ThreadSafeLinks = Arc<Mutex<Vec<LinkOutcome>>>;
+
+/// The map and the records populated from a crawled link.
+struct PopulatedLink {
+    map: HashMap<String, String>,
+    records: Vec<HashMap<String, String>>,
+}
+
+#[derive(Clone, Debug)]
+/// A crawled link, that could not be populated.
+pub struct LinkFailure {
+    pub url: String,
+    pub kind: ErrorKind,
+    /// The HTTP status, if the server responded.
+    pub status: Option<u16>,
+    pub message: String,
+}
+
+impl LinkFailure {
+    fn new(url: &str, error: &DebrisError) -> LinkFailure {
+        LinkFailure {
+            url: String::from(url),
+            kind: error.kind(),
+            status: error.status(),
+            message: error.to_string(),
+        }
     }
 }
 
-type ThreadSafeLinks = Arc<Mutex<Vec<HashMap<String, String>>>>;
+#[derive(Clone, Debug)]
+/// Limits the failed links of a crawl. Once the budget is exceeded, the crawl is aborted.
+pub enum ErrorBudget {
+    /// The number of failed links tolerated.
+    Count(usize),
+    /// The ratio (between 0 and 1) of failed links tolerated. Checked after every page of links.
+    Ratio(f64),
+}
 
 /// The populator usable on multiple identical HTML structure (link crawling).
 pub struct MultiplePopulator {
     url: String,
     /// Multiple populated map
     pub populated_links: Vec<HashMap<String, String>>,
+    /// Records of the paths starting on all the matched elements of the crawled links.
+    pub records: Vec<HashMap<String, String>>,
+    /// Links, that could not be populated. A failed link does not stop the crawl.
+    pub failed_links: Vec<LinkFailure>,
+    /// Links, that were not crawled, because robots.txt disallows them.
+    pub skipped_links: Vec<String>,
     links_path: Path,
     /// A simple converter function that takes the link as an argument. Use it when the HTML structure
     /// only contains a relative path instead of an absolute url.
@@ -95,6 +179,11 @@ pub struct MultiplePopulator {
     search_detail: SearchDetail<'static>,
     paging: Paging,
     multi_thread: bool,
+    fetcher: Arc<dyn Fetcher>,
+    error_budget: Option<ErrorBudget>,
+    max_threads: Option<usize>,
+    rate_limit: Option<RateLimit>,
+    robots: Option<Arc<Robots>>,
 }
 
 impl<'a> MultiplePopulator {
@@ -109,11 +198,19 @@ impl<'a> MultiplePopulator {
         MultiplePopulator {
             url: String::from(url),
             populated_links,
+            records: Vec::new(),
+            failed_links: Vec::new(),
+            skipped_links: Vec::new(),
             links_path,
             link_prefix: link_converter,
             search_detail: search,
             paging: Paging::Disabled,
             multi_thread,
+            fetcher: Arc::new(HttpFetcher::new()),
+            error_budget: None,
+            max_threads: None,
+            rate_limit: None,
+            robots: Some(Arc::new(Robots::new(DEFAULT_USER_AGENT))),
         }
     }
 
@@ -125,34 +222,81 @@ impl<'a> MultiplePopulator {
         multi_thread: bool,
         paging_option: PagingOptions,
     ) -> MultiplePopulator {
-        let mut html_string = reqwest::get(url).expect("Can't connect to url!");
-        let html = Html::parse_fragment(&html_string.text().unwrap_or("".to_string()));
         let populated_links = Vec::<HashMap<String, String>>::new();
         MultiplePopulator {
             url: String::from(url),
             populated_links,
+            records: Vec::new(),
+            failed_links: Vec::new(),
+            skipped_links: Vec::new(),
             links_path,
             link_prefix: link_converter,
             search_detail: search,
             paging: Paging::Enabled(paging_option),
             multi_thread,
+            fetcher: Arc::new(HttpFetcher::new()),
+            error_budget: None,
+            max_threads: None,
+            rate_limit: None,
+            robots: Some(Arc::new(Robots::new(DEFAULT_USER_AGENT))),
         }
     }
 
-    pub fn run(&mut self) -> Result<&Vec<HashMap<String, String>>, String> {
-        match &self.paging {
+    /// Replace the fetcher used for the link page and every crawled link.
+    pub fn set_fetcher(&mut self, fetcher: Arc<dyn Fetcher>) {
+        self.fetcher = fetcher;
+    }
+
+    /// Limit the number of threads crawling the links at the same time. By default it is the
+    /// `max_concurrency` of the rate limit, or [`DEFAULT_MAX_THREADS`] without one.
+    pub fn set_max_threads(&mut self, max_threads: usize) {
+        self.max_threads = Some(max_threads);
+    }
+
+    /// Limit the requests sent to a single host by the fetcher. The fetcher is throttled, when the
+    /// crawl starts. To retry through the throttle, set a `RetryFetcher::throttled` instead.
+    pub fn set_rate_limit(&mut self, limit: RateLimit) {
+        self.rate_limit = Some(limit);
+    }
+
+    /// Abort the crawl, when more links fail than the budget allows. Without a budget every link
+    /// is attempted.
+    pub fn set_error_budget(&mut self, budget: ErrorBudget) {
+        self.error_budget = Some(budget);
+    }
+
+    /// Match the robots.txt groups by this user-agent token instead of `debris`.
+    pub fn set_robots_user_agent(&mut self, user_agent: &str) {
+        self.robots = Some(Arc::new(Robots::new(user_agent)));
+    }
+
+    /// Crawl without fetching robots.txt and obeying its rules.
+    pub fn ignore_robots_txt(&mut self) {
+        self.robots = None;
+    }
+
+    /// Crawl every link and populate them. A failing link is recorded in `failed_links`, while the
+    /// crawl continues. An error is returned, if a link page can not be processed or the error
+    /// budget is exceeded.
+    pub fn run(&mut self) -> Result<&Vec<HashMap<String, String>>> {
+        if let Some(limit) = self.rate_limit.take() {
+            self.max_threads
+                .get_or_insert(cmp::max(limit.max_concurrency, 1));
+            self.fetcher = Arc::new(ThrottledFetcher::new(self.fetcher.clone(), limit));
+        }
+        match self.paging.clone() {
             Paging::Enabled(options) => match options.range {
                 PagingRange::Indefinite => {
                     let mut page = 0;
                     loop {
                         let link = format!("{}{}", self.url, options.extension)
                             .replace("{}", &page.to_string());
-                        let html = get_html(&link);
-                        let mut result = self.populate(html)?;
+                        let html = self.get_page(&link)?;
+                        let result = self.populate(html)?;
                         if result.len() == 0 {
                             break;
                         }
-                        self.populated_links.append(&mut result);
+                        self.collect_outcomes(result)?;
                         page += 1;
                     }
                 }
@@ -160,101 +304,196 @@ impl<'a> MultiplePopulator {
                     for i in 0..n {
                         let link = format!("{}{}", self.url, options.extension)
                             .replace("{}", (&i.to_string()));
-                        let html = get_html(&link);
-                        let mut result = self.populate(html)?;
-                        self.populated_links.append(&mut result);
+                        let html = self.get_page(&link)?;
+                        let result = self.populate(html)?;
+                        self.collect_outcomes(result)?;
                     }
                 }
             },
             Paging::Disabled => {
-                let html = get_html(&self.url);
-                let mut result = self.populate(html)?;
-                self.populated_links.append(&mut result);
+                let html = self.get_page(&self.url)?;
+                let result = self.populate(html)?;
+                self.collect_outcomes(result)?;
             }
         }
         Ok(&self.populated_links)
     }
 
-    fn populate(&self, html: Html) -> Result<Vec<HashMap<String, String>>, String> {
-        match self.multi_thread {
-            true => self.par_populate(html),
-            false => self.single_populate(html),
+    /// Retrieve a link page, when robots.txt allows it.
+    fn get_page(&self, url: &str) -> Result<Html> {
+        if let Some(robots) = &self.robots {
+            robots.check(self.fetcher.as_ref(), url)?;
         }
+        get_html(self.fetcher.as_ref(), url)
     }
 
-    /// Start single threaded population based on the link path.
-    fn single_populate(&self, html: Html) -> Result<Vec<HashMap<String, String>>, String> {
+    fn populate(&self, html: Html) -> Result<Vec<LinkOutcome>> {
+        let scope = DocumentScope::new(&html);
         let html = RefCell::new(&html);
-
-        let mut populated_links: Vec<HashMap<String, String>> = Vec::new();
         let mut path_finder = PathFinder::new(&self.links_path, html.borrow());
-        path_finder.search_path();
+        path_finder.search_path()?;
+        drop(scope);
 
-        for link in path_finder.values {
-            let link = match &self.link_prefix {
+        let links = path_finder
+            .values
+            .into_iter()
+            .map(|link| match &self.link_prefix {
                 Some(prefix) => prefix.clone() + &link,
                 None => link,
-            };
-
-//            let link_html = match reqwest::get(&link) {
-//                Ok(mut response) => match response.text() {
-//                    Ok(html) => html,
-//                    Err(_) => {
-//                        return Err(String::from("Unable to extract html from link"));
-//                    }
-//                },
-//                Err(_) => {
-//                    return Err(String::from("Link not found"));
-//                }
-//            };
-
-            let mut populator = SinglePopulator::new(&link, self.search_detail.clone());
-            populator.populate();
-            populated_links.push(populator.map);
+            })
+            .collect();
+
+        match self.multi_thread {
+            true => Ok(self.par_populate(links)),
+            false => Ok(self.single_populate(links)),
         }
-        Ok(populated_links)
     }
 
-    /// Start multithreaded population based on the link path.
-    fn par_populate(&self, html: Html) -> Result<Vec<HashMap<String, String>>, String> {
-        let html = RefCell::new(&html);
-        let mut path_finder = PathFinder::new(&self.links_path, html.borrow());
-        let all_results: Vec<HashMap<String, String>> = Vec::new();
-        let paralell_populated_links = Arc::new(Mutex::new(all_results));
-        path_finder.search_path();
+    /// Start single threaded population based on the link path.
+    fn single_populate(&self, links: Vec<String>) -> Vec<LinkOutcome> {
+        let mut populated_links: Vec<LinkOutcome> = Vec::new();
+        let mut failed = 0;
 
-        if path_finder.values.len() == 0 {
-            return Err(String::from("No link found"));
+        for link in links {
+            if self.is_count_exceeded(failed) {
+                break;
+            }
+            let outcome = populate_link(&link, &self.search_detail, &self.fetcher, &self.robots);
+            if is_failure(&outcome) {
+                failed += 1;
+            }
+            populated_links.push(outcome);
         }
+        populated_links
+    }
 
-        let pool = ThreadPool::new(path_finder.values.len());
+    /// Start multithreaded population based on the link path.
+    fn par_populate(&self, links: Vec<String>) -> Vec<LinkOutcome> {
+        let all_results: Vec<LinkOutcome> = Vec::new();
+        let paralell_populated_links: ThreadSafeLinks = Arc::new(Mutex::new(all_results));
+        let failed = Arc::new(AtomicUsize::new(0));
 
-        for link in path_finder.values {
-            let results = paralell_populated_links.clone();
+        if links.len() == 0 {
+            return Vec::new();
+        }
 
-            let link = match &self.link_prefix {
-                Some(prefix) => prefix.clone() + &link,
-                None => link,
-            };
+        let max_threads = self.max_threads.unwrap_or(DEFAULT_MAX_THREADS);
+        let threads = cmp::max(cmp::min(links.len(), max_threads), 1);
+        let pool = ThreadPool::new(threads);
 
+        for link in links {
+            let results = paralell_populated_links.clone();
             let search = self.search_detail.clone();
+            let fetcher = self.fetcher.clone();
+            let robots = self.robots.clone();
+            let failed = failed.clone();
+            let budget = self.remaining_count();
             pool.execute(move || {
-                let mut populator = SinglePopulator::new(&link, search);
-                populator.populate();
-                results.lock().unwrap().push(populator.map);
+                if budget.map_or(false, |b| failed.load(Ordering::SeqCst) > b) {
+                    return;
+                }
+                let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                    populate_link(&link, &search, &fetcher, &robots)
+                }))
+                .unwrap_or_else(|_| {
+                    Err(LinkFailure::new(
+                        &link,
+                        &DebrisError::Extraction(String::from("Population panicked")),
+                    ))
+                });
+                if is_failure(&outcome) {
+                    failed.fetch_add(1, Ordering::SeqCst);
+                }
+                results.lock().unwrap().push(outcome);
             });
         }
 
         pool.join();
-        let result = paralell_populated_links.lock().unwrap().clone();
-        Ok(result)
+        let mut results = paralell_populated_links.lock().unwrap();
+        results.drain(..).collect()
+    }
+
+    /// Store the outcomes of a page of links and check the error budget.
+    fn collect_outcomes(&mut self, outcomes: Vec<LinkOutcome>) -> Result<()> {
+        for outcome in outcomes {
+            match outcome {
+                Ok(link) => {
+                    self.populated_links.push(link.map);
+                    self.records.extend(link.records);
+                }
+                Err(failure) if failure.kind == ErrorKind::Disallowed => {
+                    self.skipped_links.push(failure.url)
+                }
+                Err(failure) => self.failed_links.push(failure),
+            }
+        }
+
+        let failed = self.failed_links.len();
+        let attempted = failed + self.populated_links.len();
+        let exceeded = match self.error_budget {
+            Some(ErrorBudget::Count(n)) => failed > n,
+            Some(ErrorBudget::Ratio(ratio)) => failed as f64 > ratio * attempted as f64,
+            None => false,
+        };
+        if exceeded {
+            return Err(DebrisError::Aborted { failed, attempted });
+        }
+        Ok(())
     }
 
+    /// The failures still tolerated on the current page by a count budget.
+    fn remaining_count(&self) -> Option<usize> {
+        match self.error_budget {
+            Some(ErrorBudget::Count(n)) => Some(n.saturating_sub(self.failed_links.len())),
+            _ => None,
+        }
+    }
+
+    fn is_count_exceeded(&self, failed_on_page: usize) -> bool {
+        self.remaining_count()
+            .map_or(false, |remaining| failed_on_page > remaining)
+    }
+}
+
+fn populate_link(
+    link: &str,
+    search: &SearchDetail<'static>,
+    fetcher: &Arc<dyn Fetcher>,
+    robots: &Option<Arc<Robots>>,
+) -> LinkOutcome {
+    if let Some(robots) = robots {
+        if let Err(error) = robots.check(fetcher.as_ref(), link) {
+            return Err(LinkFailure::new(link, &error));
+        }
+    }
+    let mut populator = SinglePopulator::new_with_fetcher(link, search.clone(), fetcher.clone());
+    match populator.populate() {
+        Ok(_) => Ok(PopulatedLink {
+            map: populator.map,
+            records: populator.records,
+        }),
+        Err(error) => Err(LinkFailure::new(link, &error)),
+    }
 }
 
-fn get_html(url: &str) -> Html {
-    let mut html_string = reqwest::get(url).expect("URL not found");
-    Html::parse_fragment(&html_string.text().unwrap_or("".to_string()))
+/// Skipped links are not failures.
+fn is_failure(outcome: &LinkOutcome) -> bool {
+    match outcome {
+        Err(failure) => failure.kind != ErrorKind::Disallowed,
+        Ok(_) => false,
+    }
+}
+
+fn get_html(fetcher: &dyn Fetcher, url: &str) -> Result<Html> {
+    let response = fetcher.fetch(url)?;
+    if !response.is_success() {
+        return Err(DebrisError::fetch(
+            url,
+            Some(response.status),
+            "Unsuccessful response",
+        ));
+    }
+    Ok(Html::parse_fragment(&response.body))
 }
 
 #[derive(Clone)]
@@ -267,7 +506,7 @@ pub struct SearchDetail<'a> {
 impl<'a> SearchDetail<'a> {
     pub fn new() -> Self {
         let paths = Vec::<Vec<PathStep>>::new();
-        let fields = HashMap::<&'a str, FieldIdentity>::new();
+        let fields = Fields::new();
         SearchDetail { paths, fields }
     }
 
@@ -281,11 +520,11 @@ impl<'a> SearchDetail<'a> {
         element_number: ElementSelection,
     ) {
         self.fields.insert(
-            field_name,
-            FieldIdentity {
-                destination: Destination(String::from(selector), element_number),
-                destination_location: location,
-            },
+            Cow::Borrowed(field_name),
+            FieldIdentity::new(
+                Destination(Selector::from(selector), element_number, None),
+                location,
+            ),
         );
     }
 
@@ -298,17 +537,320 @@ impl<'a> SearchDetail<'a> {
         element_number: ElementSelection,
     ) {
         self.fields.insert(
-            field_name,
-            FieldIdentity {
-                destination: Destination(String::from(selector), element_number),
-                destination_location: DestinationLocation::Attr(String::from(attr_name)),
-            },
+            Cow::Borrowed(field_name),
+            FieldIdentity::new(
+                Destination(Selector::from(selector), element_number, None),
+                DestinationLocation::Attr(String::from(attr_name)),
+            ),
         );
     }
 
+    /// Insert an already built field identity (eg. one parsed from a `FIELD(...)` definition).
+    pub fn insert_field_identity(&mut self, field_name: &str, identity: FieldIdentity) {
+        self.fields
+            .insert(Cow::Owned(String::from(field_name)), identity);
+    }
+
     /// When a field can not be distinguished (eg. a simple `<div>` element, that is unlikely to be unique), a path
     /// must be used to extract the element.
     pub fn insert_path(&mut self, path: Path) {
         self.paths.push(path);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::MapFetcher;
+    use crate::fetch::Response;
+    use crate::path::PathBuilder;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_single_populate_from_fetcher() {
+        let mut fetcher = MapFetcher::new();
+        fetcher.insert_page(
+            "http://test",
+            r#"<div class="found"><i>HELLO</i><i>WORLD</i></div>"#,
+        );
+        let mut search = SearchDetail::new();
+        search.insert_field(
+            "test",
+            r#"div[class="found"]"#,
+            DestinationLocation::Attr(String::from("class")),
+            ElementSelection::first(),
+        );
+        let mut populator =
+            SinglePopulator::new_with_fetcher("http://test", search, Arc::new(fetcher));
+
+        populator.populate().unwrap();
+
+        assert_eq!(populator.map.get("test").unwrap(), "found");
+    }
+
+    #[test]
+    fn test_single_populate_error_on_missing_page() {
+        let fetcher = MapFetcher::new();
+        let mut populator = SinglePopulator::new_with_fetcher(
+            "http://test",
+            SearchDetail::new(),
+            Arc::new(fetcher),
+        );
+
+        let result = populator.populate();
+
+        matches::assert_matches!(
+            result,
+            Err(DebrisError::Fetch {
+                status: Some(404),
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_multiple_populate_from_fetcher() {
+        let mut fetcher = MapFetcher::new();
+        fetcher.insert_page(
+            "http://test",
+            r#"<ul id="results"><a href="first">1</a><a href="second">2</a></ul>"#,
+        );
+        fetcher.insert_page("http://test/first", r#"<p>first page</p>"#);
+        fetcher.insert_page("http://test/second", r#"<p>second page</p>"#);
+        let link_path = PathBuilder::new()
+            .start(Destination::new("ul", ElementSelection::first()))
+            .find_all("a", "", DestinationLocation::Attr(String::from("href")))
+            .build();
+        let mut search = SearchDetail::new();
+        search.insert_field("text", "p", DestinationLocation::Text, ElementSelection::first());
+        let mut populator = MultiplePopulator::new(
+            "http://test",
+            link_path,
+            Some(String::from("http://test/")),
+            search,
+            false,
+        );
+        populator.set_fetcher(Arc::new(fetcher));
+
+        let populated = populator.run().unwrap();
+
+        assert_eq!(2, populated.len());
+        assert_eq!(populated[0].get("text").unwrap(), "first page");
+        assert_eq!(populated[1].get("text").unwrap(), "second page");
+    }
+
+    fn failing_link_populator(multi_thread: bool) -> MultiplePopulator {
+        let mut fetcher = MapFetcher::new();
+        fetcher.insert_page(
+            "http://test",
+            r#"<ul id="results"><a href="first">1</a><a href="missing">2</a></ul>"#,
+        );
+        fetcher.insert_page("http://test/first", r#"<p>first page</p>"#);
+        let link_path = PathBuilder::new()
+            .start(Destination::new("ul", ElementSelection::first()))
+            .find_all("a", "", DestinationLocation::Attr(String::from("href")))
+            .build();
+        let mut search = SearchDetail::new();
+        search.insert_field(
+            "text",
+            "p",
+            DestinationLocation::Text,
+            ElementSelection::first(),
+        );
+        let mut populator = MultiplePopulator::new(
+            "http://test",
+            link_path,
+            Some(String::from("http://test/")),
+            search,
+            multi_thread,
+        );
+        populator.set_fetcher(Arc::new(fetcher));
+        populator
+    }
+
+    #[test]
+    fn test_failed_link_does_not_stop_crawl() {
+        for multi_thread in &[false, true] {
+            let mut populator = failing_link_populator(*multi_thread);
+
+            assert_eq!(1, populator.run().unwrap().len());
+            assert_eq!(1, populator.failed_links.len());
+            let failure = &populator.failed_links[0];
+            assert_eq!("http://test/missing", failure.url);
+            assert_eq!(ErrorKind::Fetch, failure.kind);
+            assert_eq!(Some(404), failure.status);
+        }
+    }
+
+    /// Counts the requests in flight at the same time.
+    struct ConcurrencyFetcher {
+        inner: MapFetcher,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    impl Fetcher for ConcurrencyFetcher {
+        fn fetch(&self, url: &str) -> Result<Response> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            self.inner.fetch(url)
+        }
+    }
+
+    #[test]
+    fn test_threads_are_limited_by_default() {
+        let mut inner = MapFetcher::new();
+        let links: String = (0..12)
+            .map(|i| format!(r#"<a href="{}">{}</a>"#, i, i))
+            .collect();
+        inner.insert_page("http://test", &format!("<ul>{}</ul>", links));
+        for i in 0..12 {
+            inner.insert_page(&format!("http://test/{}", i), "<p>page</p>");
+        }
+        let fetcher = Arc::new(ConcurrencyFetcher {
+            inner,
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: AtomicUsize::new(0),
+        });
+        let link_path = PathBuilder::new()
+            .start(Destination::new("ul", ElementSelection::first()))
+            .find_all("a", "", DestinationLocation::Attr(String::from("href")))
+            .build();
+        let mut populator = MultiplePopulator::new(
+            "http://test",
+            link_path,
+            Some(String::from("http://test/")),
+            SearchDetail::new(),
+            true,
+        );
+        populator.set_fetcher(fetcher.clone());
+        populator.ignore_robots_txt();
+
+        assert_eq!(12, populator.run().unwrap().len());
+        assert!(fetcher.max_in_flight.load(Ordering::SeqCst) <= DEFAULT_MAX_THREADS);
+    }
+
+    #[test]
+    fn test_skip_links_disallowed_by_robots() {
+        let mut fetcher = MapFetcher::new();
+        fetcher.insert_page(
+            "http://test",
+            r#"<ul id="results"><a href="first">1</a><a href="private">2</a></ul>"#,
+        );
+        fetcher.insert_page(
+            "http://test/robots.txt",
+            "User-agent: *\nDisallow: /private",
+        );
+        fetcher.insert_page("http://test/first", r#"<p>first page</p>"#);
+        fetcher.insert_page("http://test/private", r#"<p>private page</p>"#);
+        let link_path = PathBuilder::new()
+            .start(Destination::new("ul", ElementSelection::first()))
+            .find_all("a", "", DestinationLocation::Attr(String::from("href")))
+            .build();
+        let mut search = SearchDetail::new();
+        search.insert_field(
+            "text",
+            "p",
+            DestinationLocation::Text,
+            ElementSelection::first(),
+        );
+        let mut populator = MultiplePopulator::new(
+            "http://test",
+            link_path,
+            Some(String::from("http://test/")),
+            search,
+            false,
+        );
+        populator.set_fetcher(Arc::new(fetcher));
+        populator.set_error_budget(ErrorBudget::Count(0));
+
+        assert_eq!(1, populator.run().unwrap().len());
+        assert_eq!(vec!["http://test/private"], populator.skipped_links);
+        assert!(populator.failed_links.is_empty());
+
+        populator.ignore_robots_txt();
+        populator.populated_links.clear();
+        populator.skipped_links.clear();
+
+        assert_eq!(2, populator.run().unwrap().len());
+    }
+
+    #[test]
+    fn test_records_of_crawled_links() {
+        let mut fetcher = MapFetcher::new();
+        fetcher.insert_page(
+            "http://test",
+            r#"<ul id="results"><a href="first">1</a><a href="second">2</a></ul>"#,
+        );
+        fetcher.insert_page(
+            "http://test/first",
+            r#"<ul><li><b>a</b></li><li><b>b</b></li></ul>"#,
+        );
+        fetcher.insert_page("http://test/second", r#"<ul><li><b>c</b></li></ul>"#);
+        let link_path = PathBuilder::new()
+            .start(Destination::new("ul", ElementSelection::first()))
+            .find_all("a", "", DestinationLocation::Attr(String::from("href")))
+            .build();
+        let detail_path = PathBuilder::new()
+            .start(Destination::new("ul", ElementSelection::first()))
+            .for_each("li")
+            .populate_one(
+                "item",
+                "b",
+                ElementSelection::first(),
+                DestinationLocation::Text,
+            )
+            .build();
+        let mut search = SearchDetail::new();
+        search.insert_path(detail_path);
+
+        for multi_thread in &[false, true] {
+            let mut populator = MultiplePopulator::new(
+                "http://test",
+                link_path.clone(),
+                Some(String::from("http://test/")),
+                search.clone(),
+                *multi_thread,
+            );
+            populator.set_fetcher(Arc::new(fetcher.clone()));
+            populator.ignore_robots_txt();
+
+            populator.run().unwrap();
+
+            let mut items: Vec<&str> = populator
+                .records
+                .iter()
+                .map(|record| record["item"].as_str())
+                .collect();
+            items.sort();
+            assert_eq!(vec!["a", "b", "c"], items);
+        }
+    }
+
+    #[test]
+    fn test_abort_on_exceeded_error_budget() {
+        let mut populator = failing_link_populator(false);
+        populator.set_error_budget(ErrorBudget::Ratio(0.25));
+
+        let result = populator.run();
+
+        matches::assert_matches!(
+            result,
+            Err(DebrisError::Aborted {
+                failed: 1,
+                attempted: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_failures_within_error_budget() {
+        let mut populator = failing_link_populator(true);
+        populator.set_error_budget(ErrorBudget::Count(1));
+
+        assert_eq!(1, populator.run().unwrap().len());
+    }
+}
@@ -0,0 +1,119 @@
+//! Recording fetched responses into a fixture directory and replaying them later
+//!
+//! Every response is stored as a JSON file named after its url, so a crawl can be repeated
+//! deterministically without network access.
+use crate::error::DebrisError;
+use crate::error::Result;
+use crate::fetch::Fetcher;
+use crate::fetch::Response;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const FIXTURE_NAME_LENGTH: usize = 64;
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Stores every response served by the inner fetcher into the fixture directory.
+pub struct RecordingFetcher {
+    inner: Arc<dyn Fetcher>,
+    directory: PathBuf,
+}
+
+impl RecordingFetcher {
+    pub fn new<P: AsRef<Path>>(inner: Arc<dyn Fetcher>, directory: P) -> Result<RecordingFetcher> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory)
+            .map_err(|e| DebrisError::Config(format!("Can not create fixture directory: {}", e)))?;
+        Ok(RecordingFetcher { inner, directory })
+    }
+}
+
+impl Fetcher for RecordingFetcher {
+    fn fetch(&self, url: &str) -> Result<Response> {
+        let response = self.inner.fetch(url)?;
+        let fixture = serde_json::to_string_pretty(&response)
+            .map_err(|e| DebrisError::fetch(url, Some(response.status), &e.to_string()))?;
+        fs::write(self.directory.join(fixture_name(url)), fixture)
+            .map_err(|e| DebrisError::fetch(url, Some(response.status), &e.to_string()))?;
+        Ok(response)
+    }
+}
+
+/// Serves the responses previously stored by a [`RecordingFetcher`].
+pub struct ReplayFetcher {
+    directory: PathBuf,
+}
+
+impl ReplayFetcher {
+    pub fn new<P: AsRef<Path>>(directory: P) -> ReplayFetcher {
+        ReplayFetcher {
+            directory: directory.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl Fetcher for ReplayFetcher {
+    fn fetch(&self, url: &str) -> Result<Response> {
+        let fixture = fs::read_to_string(self.directory.join(fixture_name(url)))
+            .map_err(|_| DebrisError::fetch(url, None, "No recorded response"))?;
+        serde_json::from_str(&fixture)
+            .map_err(|e| DebrisError::fetch(url, None, &format!("Invalid fixture: {}", e)))
+    }
+}
+
+/// The file name of an url's fixture. It consists of a readable prefix of the url and a hash of
+/// the whole url, so that long urls sharing a prefix do not collide.
+pub fn fixture_name(url: &str) -> String {
+    let readable: String = url
+        .split("://")
+        .last()
+        .unwrap_or(url)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .take(FIXTURE_NAME_LENGTH)
+        .collect();
+
+    format!("{}-{:016x}.json", readable, fnv_hash(url))
+}
+
+/// FNV-1a hash, which is stable between runs and Rust versions unlike the std hasher.
+fn fnv_hash(value: &str) -> u64 {
+    value.bytes().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::MapFetcher;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_fixture_name_is_readable_and_distinct() {
+        let first = fixture_name("https://news.ycombinator.com/item?id=1");
+        let second = fixture_name("https://news.ycombinator.com/item?id=2");
+
+        assert!(first.starts_with("news_ycombinator_com_item_id_1-"));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_replay_recorded_response() {
+        let directory = TempDir::new("record");
+        let mut pages = MapFetcher::new();
+        pages.insert_page("http://test/page", "<p>recorded</p>");
+        let recorder = RecordingFetcher::new(Arc::new(pages), directory.path()).unwrap();
+
+        let recorded = recorder.fetch("http://test/page").unwrap();
+        let replayed = ReplayFetcher::new(directory.path())
+            .fetch("http://test/page")
+            .unwrap();
+
+        assert_eq!(recorded.body, replayed.body);
+        assert_eq!(recorded.status, replayed.status);
+        assert_eq!("http://test/page", replayed.url);
+    }
+}
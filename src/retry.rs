@@ -0,0 +1,340 @@
+//! Retrying failed requests with exponential backoff
+//!
+use crate::error::DebrisError;
+use crate::error::IoFailure;
+use crate::error::Result;
+use crate::fetch::Fetcher;
+use crate::fetch::Response;
+use crate::throttle::is_throttled;
+use crate::throttle::ThrottledFetcher;
+use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::hash::Hasher;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+/// Describes which requests are repeated and how long to wait between the attempts. The n-th
+/// retry waits `base_delay_ms * 2^(n-1)` milliseconds (at most `max_delay_ms`), extended by a random
+/// `jitter` fraction of the delay.
+pub struct RetryPolicy {
+    /// The number of requests made for an url, including the first one.
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    /// Between 0 and 1.
+    pub jitter: f64,
+    /// Responses with these statuses are retried.
+    pub retry_statuses: Vec<u16>,
+    /// Connection failures of these kinds are retried.
+    pub retry_io: Vec<IoFailure>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+            jitter: 0.1,
+            retry_statuses: vec![429, 500, 502, 503, 504],
+            retry_io: vec![IoFailure::Timeout, IoFailure::Connect, IoFailure::Reset],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> RetryPolicy {
+        RetryPolicy::default()
+    }
+
+    pub fn max_attempts(&mut self, attempts: u32) -> &mut Self {
+        self.max_attempts = attempts;
+        return self;
+    }
+
+    pub fn base_delay(&mut self, delay: Duration) -> &mut Self {
+        self.base_delay_ms = delay.as_millis() as u64;
+        return self;
+    }
+
+    pub fn max_delay(&mut self, delay: Duration) -> &mut Self {
+        self.max_delay_ms = delay.as_millis() as u64;
+        return self;
+    }
+
+    pub fn jitter(&mut self, jitter: f64) -> &mut Self {
+        self.jitter = jitter;
+        return self;
+    }
+
+    /// Replace the retried statuses.
+    pub fn retry_statuses(&mut self, statuses: &[u16]) -> &mut Self {
+        self.retry_statuses = statuses.to_vec();
+        return self;
+    }
+
+    /// Replace the retried connection failures.
+    pub fn retry_io(&mut self, failures: &[IoFailure]) -> &mut Self {
+        self.retry_io = failures.to_vec();
+        return self;
+    }
+
+    /// Returns the constructed policy
+    pub fn build(&self) -> RetryPolicy {
+        self.clone()
+    }
+
+    /// Decides whether the outcome of a request is worth another attempt.
+    pub fn is_retryable(&self, outcome: &Result<Response>) -> bool {
+        match outcome {
+            Ok(response) => self.retry_statuses.contains(&response.status),
+            Err(DebrisError::Fetch {
+                status: Some(status),
+                ..
+            }) => self.retry_statuses.contains(status),
+            Err(DebrisError::Fetch { io: Some(io), .. }) => self.retry_io.contains(io),
+            Err(_) => false,
+        }
+    }
+
+    /// The delay before the given retry (starting from 1).
+    pub fn delay(&self, retry: u32) -> Duration {
+        let exponent = cmp::min(retry.saturating_sub(1), 32);
+        let delay = self
+            .base_delay_ms
+            .saturating_mul(1u64 << exponent)
+            .min(self.max_delay_ms);
+        let jitter = (delay as f64 * self.jitter * random_fraction()) as u64;
+
+        Duration::from_millis(delay + jitter)
+    }
+}
+
+/// Repeats the requests of the inner fetcher according to a [`RetryPolicy`]. When the server
+/// sends a `Retry-After` header, it is waited at least that long, but never longer than the
+/// maximal delay of the policy.
+pub struct RetryFetcher {
+    inner: Arc<dyn Fetcher>,
+    policy: RetryPolicy,
+    throttled: bool,
+}
+
+impl RetryFetcher {
+    pub fn new(inner: Arc<dyn Fetcher>, policy: RetryPolicy) -> RetryFetcher {
+        RetryFetcher {
+            inner,
+            policy,
+            throttled: false,
+        }
+    }
+
+    /// Repeats the requests of a throttled fetcher. A `429` (or a `503` with `Retry-After`) is
+    /// retried without sleeping, as the throttle already delays the next request to the host.
+    pub fn throttled(inner: Arc<ThrottledFetcher>, policy: RetryPolicy) -> RetryFetcher {
+        RetryFetcher {
+            inner,
+            policy,
+            throttled: true,
+        }
+    }
+
+    fn delay(&self, attempt: u32, outcome: &Result<Response>) -> Duration {
+        let response = match outcome {
+            Ok(response) => response,
+            Err(_) => return self.policy.delay(attempt),
+        };
+        if self.throttled && is_throttled(response) {
+            return Duration::from_secs(0);
+        }
+        let max_delay = Duration::from_millis(self.policy.max_delay_ms);
+        match retry_after(response) {
+            Some(retry_after) => {
+                cmp::max(self.policy.delay(attempt), cmp::min(retry_after, max_delay))
+            }
+            None => self.policy.delay(attempt),
+        }
+    }
+}
+
+impl Fetcher for RetryFetcher {
+    fn fetch(&self, url: &str) -> Result<Response> {
+        let mut attempt = 1;
+        loop {
+            let outcome = self.inner.fetch(url);
+            if attempt >= self.policy.max_attempts || !self.policy.is_retryable(&outcome) {
+                return outcome;
+            }
+
+            thread::sleep(self.delay(attempt, &outcome));
+            attempt += 1;
+        }
+    }
+}
+
+/// Reads the `Retry-After` header given in seconds.
+pub fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .header("Retry-After")
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// A random number between 0 and 1. The randomly keyed std hasher is enough for spreading delays.
+fn random_fraction() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::throttle::RateLimit;
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    struct FlakyFetcher {
+        statuses: Mutex<Vec<u16>>,
+        attempts: Mutex<u32>,
+        /// Sent with the `429` responses.
+        retry_after: Option<String>,
+    }
+
+    impl Fetcher for FlakyFetcher {
+        fn fetch(&self, url: &str) -> Result<Response> {
+            *self.attempts.lock().unwrap() += 1;
+            let status = self.statuses.lock().unwrap().remove(0);
+            let mut response = Response {
+                status,
+                ..Response::new(url, "<p>page</p>")
+            };
+            if let (429, Some(retry_after)) = (status, &self.retry_after) {
+                response
+                    .headers
+                    .push((String::from("Retry-After"), retry_after.clone()));
+            }
+            Ok(response)
+        }
+    }
+
+    fn flaky(statuses: &[u16]) -> Arc<FlakyFetcher> {
+        too_many_requests(statuses, None)
+    }
+
+    fn too_many_requests(statuses: &[u16], retry_after: Option<&str>) -> Arc<FlakyFetcher> {
+        Arc::new(FlakyFetcher {
+            statuses: Mutex::new(statuses.to_vec()),
+            attempts: Mutex::new(0),
+            retry_after: retry_after.map(String::from),
+        })
+    }
+
+    #[test]
+    fn test_retry_until_success() {
+        let inner = flaky(&[503, 502, 200]);
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_millis(1))
+            .build();
+        let fetcher = RetryFetcher::new(inner.clone(), policy);
+
+        let response = fetcher.fetch("http://test").unwrap();
+
+        assert_eq!(200, response.status);
+        assert_eq!(3, *inner.attempts.lock().unwrap());
+    }
+
+    #[test]
+    fn test_no_retry_on_non_retryable_status() {
+        let inner = flaky(&[404, 200]);
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_millis(1))
+            .build();
+        let fetcher = RetryFetcher::new(inner.clone(), policy);
+
+        let response = fetcher.fetch("http://test").unwrap();
+
+        assert_eq!(404, response.status);
+        assert_eq!(1, *inner.attempts.lock().unwrap());
+    }
+
+    #[test]
+    fn test_stop_after_max_attempts() {
+        let inner = flaky(&[503, 503, 503]);
+        let policy = RetryPolicy::new()
+            .max_attempts(2)
+            .base_delay(Duration::from_millis(1))
+            .build();
+        let fetcher = RetryFetcher::new(inner.clone(), policy);
+
+        let response = fetcher.fetch("http://test").unwrap();
+
+        assert_eq!(503, response.status);
+        assert_eq!(2, *inner.attempts.lock().unwrap());
+    }
+
+    #[test]
+    fn test_retry_after_is_capped_by_max_delay() {
+        let inner = too_many_requests(&[429, 200], Some("86400"));
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_millis(1))
+            .max_delay(Duration::from_millis(10))
+            .build();
+        let fetcher = RetryFetcher::new(inner.clone(), policy);
+        let start = Instant::now();
+
+        let response = fetcher.fetch("http://test").unwrap();
+
+        assert_eq!(200, response.status);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_throttle_delays_retry_of_throttled_response() {
+        let inner = too_many_requests(&[429, 200], Some("0"));
+        let throttled = Arc::new(ThrottledFetcher::new(inner, RateLimit::new(1000.0, 1)));
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_secs(60))
+            .jitter(0.0)
+            .build();
+        let fetcher = RetryFetcher::throttled(throttled, policy);
+        let start = Instant::now();
+
+        let response = fetcher.fetch("http://test").unwrap();
+
+        assert_eq!(200, response.status);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_retryable_io_failure() {
+        let policy = RetryPolicy::new().retry_io(&[IoFailure::Timeout]).build();
+
+        assert!(policy.is_retryable(&Err(DebrisError::io(
+            "http://test",
+            IoFailure::Timeout,
+            "timed out"
+        ))));
+        assert!(!policy.is_retryable(&Err(DebrisError::io(
+            "http://test",
+            IoFailure::Connect,
+            "refused"
+        ))));
+    }
+
+    #[test]
+    fn test_exponential_delay() {
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(300))
+            .jitter(0.0)
+            .build();
+
+        assert_eq!(Duration::from_millis(100), policy.delay(1));
+        assert_eq!(Duration::from_millis(200), policy.delay(2));
+        assert_eq!(Duration::from_millis(300), policy.delay(3));
+    }
+}
@@ -0,0 +1,429 @@
+//! robots.txt compliance of crawls
+//!
+//! The rules of every host are fetched once and cached. A group is selected by the user-agent
+//! token (falling back to `*`), and the longest matching `Allow`/`Disallow` pattern decides about a
+//! path. `Crawl-delay` spaces out the requests sent to the host.
+use crate::error::DebrisError;
+use crate::error::Result;
+use crate::fetch::Fetcher;
+use reqwest::Url;
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const DEFAULT_USER_AGENT: &str = "debris";
+const ROBOTS_PATH: &str = "/robots.txt";
+/// Upper bound of a `Crawl-delay`, so a host can not stall the crawl.
+pub const MAX_CRAWL_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, Default)]
+struct Group {
+    agents: Vec<String>,
+    /// Patterns with whether they allow the path.
+    rules: Vec<(String, bool)>,
+    crawl_delay: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+/// The parsed content of a robots.txt file.
+pub struct RobotsRules {
+    groups: Vec<Group>,
+    /// Used, when robots.txt could not be retrieved because of a server error.
+    disallow_all: bool,
+}
+
+impl RobotsRules {
+    pub fn parse(content: &str) -> RobotsRules {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut current = Group::default();
+        let mut in_agent_lines = false;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let separator = match line.find(':') {
+                Some(i) => i,
+                None => continue,
+            };
+            let key = line[..separator].trim().to_lowercase();
+            let value = line[separator + 1..].trim();
+
+            match key.as_str() {
+                "user-agent" => {
+                    if !in_agent_lines && !current.agents.is_empty() {
+                        groups.push(current);
+                        current = Group::default();
+                    }
+                    current.agents.push(value.to_lowercase());
+                    in_agent_lines = true;
+                }
+                "allow" | "disallow" => {
+                    in_agent_lines = false;
+                    if !value.is_empty() {
+                        current.rules.push((String::from(value), key == "allow"));
+                    }
+                }
+                "crawl-delay" => {
+                    in_agent_lines = false;
+                    current.crawl_delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|d| *d >= 0.0)
+                        .map(|d| Duration::from_millis((d * 1000.0) as u64))
+                        .map(|d| cmp::min(d, MAX_CRAWL_DELAY));
+                }
+                _ => (),
+            }
+        }
+        if !current.agents.is_empty() {
+            groups.push(current);
+        }
+
+        RobotsRules {
+            groups,
+            disallow_all: false,
+        }
+    }
+
+    /// Rules allowing every path.
+    pub fn allow_all() -> RobotsRules {
+        RobotsRules {
+            groups: Vec::new(),
+            disallow_all: false,
+        }
+    }
+
+    /// Rules forbidding every path.
+    pub fn disallow_all() -> RobotsRules {
+        RobotsRules {
+            groups: Vec::new(),
+            disallow_all: true,
+        }
+    }
+
+    pub fn is_allowed(&self, user_agent: &str, path: &str) -> bool {
+        if self.disallow_all {
+            return false;
+        }
+        if path == ROBOTS_PATH {
+            return true;
+        }
+        let group = match self.group(user_agent) {
+            Some(group) => group,
+            None => return true,
+        };
+
+        let mut decision: Option<(usize, bool)> = None;
+        for (pattern, allow) in &group.rules {
+            if !pattern_matches(pattern, path) {
+                continue;
+            }
+            let length = pattern.len();
+            decision = match decision {
+                Some((longest, _)) if longest > length => decision,
+                Some((longest, allowed)) if longest == length => Some((length, allowed || *allow)),
+                _ => Some((length, *allow)),
+            };
+        }
+        decision.map_or(true, |(_, allowed)| allowed)
+    }
+
+    pub fn crawl_delay(&self, user_agent: &str) -> Option<Duration> {
+        self.group(user_agent).and_then(|group| group.crawl_delay)
+    }
+
+    /// The group with the longest agent name contained in the user agent, or the `*` group.
+    fn group(&self, user_agent: &str) -> Option<&Group> {
+        let user_agent = user_agent.to_lowercase();
+        let mut best: Option<(usize, &Group)> = None;
+        for group in &self.groups {
+            for agent in &group.agents {
+                let specificity = if agent == "*" {
+                    0
+                } else if user_agent.contains(agent.as_str()) {
+                    agent.len()
+                } else {
+                    continue;
+                };
+                if best.map_or(true, |(current, _)| specificity > current) {
+                    best = Some((specificity, group));
+                }
+            }
+        }
+        best.map(|(_, group)| group)
+    }
+}
+
+/// Matches a robots.txt path pattern supporting the `*` wildcard and the `$` end anchor.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.ends_with('$') {
+        true => (&pattern[..pattern.len() - 1], true),
+        false => (pattern, false),
+    };
+    let parts: Vec<&str> = pattern.split('*').collect();
+
+    let mut position = 0;
+    for (i, part) in parts.iter().enumerate() {
+        if i == 0 {
+            if !path.starts_with(part) {
+                return false;
+            }
+            position = part.len();
+        } else if i == parts.len() - 1 && anchored {
+            return path.len() >= position + part.len() && path.ends_with(part);
+        } else {
+            match path[position..].find(part) {
+                Some(found) => position += found + part.len(),
+                None => return false,
+            }
+        }
+    }
+
+    !anchored || position == path.len() || parts.len() > 1 && parts[parts.len() - 1].is_empty()
+}
+
+/// The rules of an origin, locked while its robots.txt is fetched.
+type RulesSlot = Arc<Mutex<Option<Arc<RobotsRules>>>>;
+
+/// Checks urls against the robots.txt of their host and applies its `Crawl-delay`.
+pub struct Robots {
+    user_agent: String,
+    rules: Mutex<HashMap<String, RulesSlot>>,
+    next_slots: Mutex<HashMap<String, Instant>>,
+}
+
+impl Robots {
+    pub fn new(user_agent: &str) -> Robots {
+        Robots {
+            user_agent: String::from(user_agent),
+            rules: Mutex::new(HashMap::new()),
+            next_slots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
+
+    /// Fails with [`DebrisError::Disallowed`], when the url may not be crawled. Otherwise waits
+    /// until the crawl delay of the host elapsed.
+    pub fn check(&self, fetcher: &dyn Fetcher, url: &str) -> Result<()> {
+        let parsed = match Url::parse(url) {
+            Ok(parsed) => parsed,
+            Err(_) => return Ok(()),
+        };
+        let origin = parsed.origin().ascii_serialization();
+        let rules = self.rules_of(fetcher, &origin);
+
+        let mut path = String::from(parsed.path());
+        if let Some(query) = parsed.query() {
+            path = format!("{}?{}", path, query);
+        }
+        if !rules.is_allowed(&self.user_agent, &path) {
+            return Err(DebrisError::Disallowed(String::from(url)));
+        }
+
+        if let Some(delay) = rules.crawl_delay(&self.user_agent) {
+            let wait = {
+                let mut next_slots = self.next_slots.lock().unwrap();
+                let now = Instant::now();
+                let slot = next_slots.get(&origin).map_or(now, |s| cmp::max(*s, now));
+                next_slots.insert(origin, slot + delay);
+                slot - now
+            };
+            thread::sleep(wait);
+        }
+        Ok(())
+    }
+
+    fn rules_of(&self, fetcher: &dyn Fetcher, origin: &str) -> Arc<RobotsRules> {
+        let slot = self
+            .rules
+            .lock()
+            .unwrap()
+            .entry(String::from(origin))
+            .or_insert_with(|| Arc::new(Mutex::new(None)))
+            .clone();
+        // Concurrent checks of the same origin wait here, until the first one fetched the rules
+        let mut rules = slot.lock().unwrap();
+        if let Some(rules) = rules.as_ref() {
+            return rules.clone();
+        }
+
+        // The fetcher retries according to its policy, only the final outcome is classified
+        let fetched = match fetcher.fetch(&format!("{}{}", origin, ROBOTS_PATH)) {
+            Ok(response) if response.is_success() => RobotsRules::parse(&response.body),
+            Ok(response) if response.status >= 500 => RobotsRules::disallow_all(),
+            Err(DebrisError::Fetch {
+                status: Some(status),
+                ..
+            }) if status >= 500 => RobotsRules::disallow_all(),
+            Err(DebrisError::Fetch { io: Some(_), .. }) => RobotsRules::disallow_all(),
+            // Missing like a 404 (eg. a file or a recorded response, that does not exist)
+            _ => RobotsRules::allow_all(),
+        };
+        let fetched = Arc::new(fetched);
+        *rules = Some(fetched.clone());
+        fetched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::IoFailure;
+    use crate::fetch::FileFetcher;
+    use crate::fetch::MapFetcher;
+    use crate::fetch::Response;
+    use crate::record::ReplayFetcher;
+    use crate::test_util::TempDir;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    const ROBOTS: &str = "
+# comment
+User-agent: *
+Disallow: /private
+Allow: /private/public
+Disallow: /*.pdf$
+
+User-agent: Debris
+User-agent: other
+Disallow: /
+Allow: /open
+Crawl-delay: 0.01
+";
+
+    #[test]
+    fn test_wildcard_group_rules() {
+        let rules = RobotsRules::parse(ROBOTS);
+
+        assert!(rules.is_allowed("crawler", "/index.html"));
+        assert!(!rules.is_allowed("crawler", "/private/secret"));
+        assert!(rules.is_allowed("crawler", "/private/public/page"));
+        assert!(!rules.is_allowed("crawler", "/files/report.pdf"));
+        assert!(rules.is_allowed("crawler", "/files/report.pdf?download"));
+        assert_eq!(None, rules.crawl_delay("crawler"));
+    }
+
+    #[test]
+    fn test_user_agent_group_rules() {
+        let rules = RobotsRules::parse(ROBOTS);
+
+        assert!(!rules.is_allowed("debris/0.1", "/index.html"));
+        assert!(rules.is_allowed("debris/0.1", "/open/page"));
+        assert!(!rules.is_allowed("other", "/index.html"));
+        assert_eq!(
+            Some(Duration::from_millis(10)),
+            rules.crawl_delay("debris/0.1")
+        );
+    }
+
+    #[test]
+    fn test_check_fetches_robots_of_host() {
+        let mut fetcher = MapFetcher::new();
+        fetcher.insert_page("http://test/robots.txt", ROBOTS);
+        let robots = Robots::new("debris");
+
+        let disallowed = robots.check(&fetcher, "http://test/index.html");
+        let allowed = robots.check(&fetcher, "http://test/open");
+        let missing_robots = robots.check(&fetcher, "http://other/index.html");
+
+        matches::assert_matches!(disallowed, Err(DebrisError::Disallowed(_)));
+        matches::assert_matches!(allowed, Ok(()));
+        matches::assert_matches!(missing_robots, Ok(()));
+    }
+
+    struct FailingFetcher {
+        outcome: Result<Response>,
+    }
+
+    impl Fetcher for FailingFetcher {
+        fn fetch(&self, _url: &str) -> Result<Response> {
+            self.outcome.clone()
+        }
+    }
+
+    #[test]
+    fn test_unavailable_robots_disallows_all() {
+        let mut unavailable = Response::new("http://test/robots.txt", "");
+        unavailable.status = 503;
+        let fetchers = vec![
+            FailingFetcher {
+                outcome: Ok(unavailable),
+            },
+            FailingFetcher {
+                outcome: Err(DebrisError::io(
+                    "http://test/robots.txt",
+                    IoFailure::Connect,
+                    "Connection refused",
+                )),
+            },
+        ];
+
+        for fetcher in fetchers {
+            matches::assert_matches!(
+                Robots::new("debris").check(&fetcher, "http://test/index.html"),
+                Err(DebrisError::Disallowed(_))
+            );
+        }
+    }
+
+    #[test]
+    fn test_crawl_delay_is_capped() {
+        let rules = RobotsRules::parse("User-agent: *\nCrawl-delay: 86400\n");
+
+        assert_eq!(Some(MAX_CRAWL_DELAY), rules.crawl_delay("debris"));
+    }
+
+    struct CountingFetcher {
+        fetches: AtomicUsize,
+    }
+
+    impl Fetcher for CountingFetcher {
+        fn fetch(&self, url: &str) -> Result<Response> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            Ok(Response::new(url, "User-agent: *\nDisallow: /private\n"))
+        }
+    }
+
+    #[test]
+    fn test_concurrent_checks_fetch_robots_once() {
+        let fetcher = Arc::new(CountingFetcher {
+            fetches: AtomicUsize::new(0),
+        });
+        let robots = Arc::new(Robots::new("debris"));
+
+        let workers: Vec<_> = (0..4)
+            .map(|i| {
+                let fetcher = fetcher.clone();
+                let robots = robots.clone();
+                thread::spawn(move || {
+                    robots.check(fetcher.as_ref(), &format!("http://test/page/{}", i))
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            matches::assert_matches!(worker.join().unwrap(), Ok(()));
+        }
+        assert_eq!(1, fetcher.fetches.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_missing_robots_allows_all() {
+        let directory = TempDir::new("robots");
+        let replay = ReplayFetcher::new(directory.path());
+        let robots = Robots::new("debris");
+
+        let replayed = robots.check(&replay, "http://test/index.html");
+        let local = robots.check(&FileFetcher::new(), "file:///missing/index.html");
+
+        matches::assert_matches!(replayed, Ok(()));
+        matches::assert_matches!(local, Ok(()));
+    }
+}
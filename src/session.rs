@@ -0,0 +1,484 @@
+//! Sessions of logged in crawls
+//!
+//! A session owns a cookie jar shared by every request (and thread) of a population. It might log
+//! in by posting a form before the population starts, and persist its cookies between runs.
+use crate::error::DebrisError;
+use crate::error::Result;
+use crate::fetch::Fetcher;
+use crate::fetch::HttpFetcher;
+use crate::fetch::Response;
+use cookie::Cookie;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// The session settings of a population.
+pub struct SessionConfig {
+    /// The form posted before the population.
+    pub login: Option<LoginForm>,
+    /// The cookies are loaded from and saved into this JSON file.
+    pub cookie_file: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A login form posted url-encoded.
+pub struct LoginForm {
+    pub url: String,
+    /// Fields posted with a fixed value.
+    #[serde(default)]
+    pub fields: HashMap<String, String>,
+    /// Fields posted with the value of an environment variable, keyed by the field name. Keeps
+    /// the credentials out of the config.
+    #[serde(default)]
+    pub credentials: HashMap<String, String>,
+}
+
+impl LoginForm {
+    /// The fields to post, with the credentials read from the environment.
+    pub fn form_fields(&self) -> Result<Vec<(String, String)>> {
+        let mut fields: Vec<(String, String)> = self
+            .fields
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        for (field, variable) in &self.credentials {
+            let value = env::var(variable).map_err(|_| {
+                DebrisError::Config(format!("Missing environment variable: {}", variable))
+            })?;
+            fields.push((field.clone(), value));
+        }
+        fields.sort();
+
+        Ok(fields)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct StoredCookie {
+    name: String,
+    value: String,
+    domain: String,
+    /// Sent only to the exact host, when the cookie had no `Domain` attribute.
+    host_only: bool,
+    path: String,
+    secure: bool,
+    /// Unix timestamp in seconds. Session cookies have none.
+    expires: Option<i64>,
+}
+
+impl StoredCookie {
+    fn is_expired(&self, now: i64) -> bool {
+        self.expires.map_or(false, |expires| expires <= now)
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let host = url.host_str().unwrap_or("").to_lowercase();
+        let domain_matches = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(&host, &self.domain)
+        };
+        domain_matches
+            && path_match(url.path(), &self.path)
+            && (!self.secure || url.scheme() == "https")
+    }
+}
+
+/// Cookies received from `Set-Cookie` headers, shared between threads.
+pub struct CookieJar {
+    cookies: Mutex<Vec<StoredCookie>>,
+}
+
+impl CookieJar {
+    pub fn new() -> CookieJar {
+        CookieJar {
+            cookies: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Loads the cookies saved into the file. A missing file gives an empty jar.
+    pub fn load(file: &str) -> Result<CookieJar> {
+        let content = match fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(CookieJar::new()),
+            Err(e) => {
+                return Err(DebrisError::Config(format!(
+                    "Can not read cookie file: {}",
+                    e
+                )))
+            }
+        };
+        let cookies: Vec<StoredCookie> = serde_json::from_str(&content)
+            .map_err(|e| DebrisError::Config(format!("Invalid cookie file: {}", e)))?;
+        Ok(CookieJar {
+            cookies: Mutex::new(cookies),
+        })
+    }
+
+    /// Saves the cookies, that did not expire, into the file.
+    pub fn save(&self, file: &str) -> Result<()> {
+        let now = now();
+        let cookies: Vec<StoredCookie> = self
+            .cookies
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|cookie| !cookie.is_expired(now))
+            .cloned()
+            .collect();
+        let content = serde_json::to_string_pretty(&cookies)
+            .map_err(|e| DebrisError::Config(e.to_string()))?;
+        fs::write(file, content)
+            .map_err(|e| DebrisError::Config(format!("Can not save cookie file: {}", e)))
+    }
+
+    /// Stores the cookie of a `Set-Cookie` header received from the url. An expired cookie
+    /// removes the stored one.
+    pub fn store(&self, url: &str, set_cookie: &str) {
+        let url = match Url::parse(url) {
+            Ok(url) => url,
+            Err(_) => return,
+        };
+        let parsed = match Cookie::parse(set_cookie) {
+            Ok(parsed) => parsed,
+            Err(_) => return,
+        };
+        let host = url.host_str().unwrap_or("").to_lowercase();
+        let (domain, host_only) = match parsed.domain() {
+            Some(domain) => (domain.trim_start_matches('.').to_lowercase(), false),
+            None => (host.clone(), true),
+        };
+        if !domain_match(&host, &domain) {
+            return;
+        }
+
+        let now = now();
+        let expires = match (parsed.max_age(), parsed.expires()) {
+            (Some(max_age), _) => Some(now + max_age.num_seconds()),
+            (None, Some(expires)) => Some(expires.to_timespec().sec),
+            (None, None) => None,
+        };
+        let cookie = StoredCookie {
+            name: String::from(parsed.name()),
+            value: String::from(parsed.value()),
+            domain,
+            host_only,
+            path: parsed
+                .path()
+                .map_or_else(|| default_path(url.path()), String::from),
+            secure: parsed.secure().unwrap_or(false),
+            expires,
+        };
+
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|stored| {
+            stored.name != cookie.name
+                || stored.domain != cookie.domain
+                || stored.path != cookie.path
+        });
+        if !cookie.is_expired(now) {
+            cookies.push(cookie);
+        }
+    }
+
+    /// The value of the `Cookie` header sent to the url.
+    pub fn cookie_header(&self, url: &str) -> Option<String> {
+        let url = Url::parse(url).ok()?;
+        let now = now();
+        let cookies: Vec<String> = self
+            .cookies
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|cookie| !cookie.is_expired(now) && cookie.matches(&url))
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect();
+
+        match cookies.is_empty() {
+            true => None,
+            false => Some(cookies.join("; ")),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cookies.lock().unwrap().len()
+    }
+}
+
+#[derive(Clone)]
+/// A cookie jar with an optional login step and persistence.
+pub struct Session {
+    jar: Arc<CookieJar>,
+    cookie_file: Option<String>,
+}
+
+impl Session {
+    /// Opens the session with the cookies saved into the cookie file of the config.
+    pub fn open(config: &SessionConfig) -> Result<Session> {
+        let jar = match &config.cookie_file {
+            Some(file) => CookieJar::load(file)?,
+            None => CookieJar::new(),
+        };
+        Ok(Session {
+            jar: Arc::new(jar),
+            cookie_file: config.cookie_file.clone(),
+        })
+    }
+
+    pub fn jar(&self) -> Arc<CookieJar> {
+        self.jar.clone()
+    }
+
+    /// Posts the login form through a fetcher, that uses the jar of this session.
+    pub fn login(&self, fetcher: &HttpFetcher, form: &LoginForm) -> Result<()> {
+        let response = fetcher.submit_form(&form.url, &form.form_fields()?)?;
+        if !response.is_success() {
+            return Err(DebrisError::fetch(
+                &form.url,
+                Some(response.status),
+                "Login failed",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Saves the cookies, when the session has a cookie file.
+    pub fn save(&self) -> Result<()> {
+        match &self.cookie_file {
+            Some(file) => self.jar.save(file),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Posts the login form of a session before the first request of the inner fetcher, so the
+/// login happens when the population runs instead of when it is built.
+pub struct LoginFetcher {
+    inner: Arc<dyn Fetcher>,
+    session: Session,
+    /// Posts the form into the jar of the session.
+    form_fetcher: HttpFetcher,
+    form: LoginForm,
+    /// The outcome of the login, once it was attempted.
+    outcome: Mutex<Option<Result<()>>>,
+}
+
+impl LoginFetcher {
+    pub fn new(
+        inner: Arc<dyn Fetcher>,
+        session: Session,
+        form_fetcher: HttpFetcher,
+        form: LoginForm,
+    ) -> LoginFetcher {
+        LoginFetcher {
+            inner,
+            session,
+            form_fetcher,
+            form,
+            outcome: Mutex::new(None),
+        }
+    }
+
+    /// Logs in once and saves the cookies. Every later request gets the outcome of that login.
+    fn login(&self) -> Result<()> {
+        let mut outcome = self
+            .outcome
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if outcome.is_none() {
+            let login = self.session.login(&self.form_fetcher, &self.form);
+            *outcome = Some(login.and_then(|_| self.session.save()));
+        }
+        outcome.clone().unwrap_or(Ok(()))
+    }
+}
+
+impl Fetcher for LoginFetcher {
+    fn fetch(&self, url: &str) -> Result<Response> {
+        self.login()?;
+        self.inner.fetch(url)
+    }
+}
+
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || (path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/')))
+}
+
+/// The directory of the request path, used when the cookie has no `Path` attribute.
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => String::from("/"),
+        Some(i) => String::from(&path[..i]),
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::RequestSettings;
+    use crate::test_util::TempDir;
+    use std::io::BufRead;
+    use std::io::BufReader;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_cookie_jar_matching() {
+        let jar = CookieJar::new();
+        jar.store("http://example.com/account/login", "sid=1; Path=/account");
+        jar.store("http://example.com/", "lang=hu; Domain=.example.com");
+        jar.store("http://example.com/", "token=2; Secure");
+        jar.store("http://example.com/", "old=3; Max-Age=0");
+
+        assert_eq!(
+            Some(String::from("sid=1; lang=hu")),
+            jar.cookie_header("http://example.com/account/orders")
+        );
+        assert_eq!(
+            Some(String::from("lang=hu")),
+            jar.cookie_header("http://www.example.com/accounts")
+        );
+        assert_eq!(
+            Some(String::from("lang=hu; token=2")),
+            jar.cookie_header("https://example.com/")
+        );
+        assert_eq!(None, jar.cookie_header("http://other.com/"));
+        assert_eq!(3, jar.len());
+    }
+
+    #[test]
+    fn test_cookie_jar_persistence() {
+        let directory = TempDir::new("cookies");
+        let file = directory.join("cookies.json");
+        let file = file.to_str().unwrap();
+        let jar = CookieJar::new();
+        jar.store("http://example.com/", "sid=1");
+        jar.save(file).unwrap();
+
+        let loaded = CookieJar::load(file).unwrap();
+
+        assert_eq!(
+            Some(String::from("sid=1")),
+            loaded.cookie_header("http://example.com/")
+        );
+        fs::remove_file(file).unwrap();
+        assert_eq!(0, CookieJar::load(file).unwrap().len());
+        // A directory can not be read as a cookie file
+        let unreadable = CookieJar::load(directory.path().to_str().unwrap());
+        assert!(matches::matches!(unreadable, Err(DebrisError::Config(_))));
+    }
+
+    #[test]
+    fn test_error_on_missing_credential() {
+        let mut form = LoginForm {
+            url: String::from("http://test/login"),
+            fields: HashMap::new(),
+            credentials: HashMap::new(),
+        };
+        form.credentials.insert(
+            String::from("password"),
+            String::from("DEBRIS_TEST_MISSING_PASSWORD"),
+        );
+
+        matches::assert_matches!(form.form_fields(), Err(DebrisError::Config(_)));
+    }
+
+    /// Answers the login with a redirect setting the session cookie, and serves the member page
+    /// only with that cookie.
+    fn serve_login(listener: TcpListener) {
+        for _ in 0..3 {
+            let mut stream = listener.accept().unwrap().0;
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                request.push(line.trim().to_lowercase());
+            }
+            let response = if request[0].starts_with("post /login") {
+                "HTTP/1.1 302 Found\r\nSet-Cookie: sid=secret; Path=/\r\nLocation: /members\r\n\
+                 Connection: close\r\nContent-Length: 0\r\n\r\n"
+            } else if request.iter().any(|h| h == "cookie: sid=secret") {
+                "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 7\r\n\r\nmembers"
+            } else {
+                "HTTP/1.1 403 Forbidden\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_login_shares_cookies() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || serve_login(listener));
+        let session = Session::open(&SessionConfig {
+            login: None,
+            cookie_file: None,
+        })
+        .unwrap();
+        let fetcher = HttpFetcher::with_session(&RequestSettings::new(), session.jar()).unwrap();
+        let form = LoginForm {
+            url: format!("{}/login", base),
+            fields: HashMap::new(),
+            credentials: HashMap::new(),
+        };
+
+        session.login(&fetcher, &form).unwrap();
+        let members = fetcher.fetch(&format!("{}/members", base)).unwrap();
+
+        assert_eq!("members", members.body);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_login_before_first_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || serve_login(listener));
+        let session = Session::open(&SessionConfig {
+            login: None,
+            cookie_file: None,
+        })
+        .unwrap();
+        let fetcher = HttpFetcher::with_session(&RequestSettings::new(), session.jar()).unwrap();
+        let form = LoginForm {
+            url: format!("{}/login", base),
+            fields: HashMap::new(),
+            credentials: HashMap::new(),
+        };
+        let login = LoginFetcher::new(Arc::new(fetcher.clone()), session.clone(), fetcher, form);
+
+        assert_eq!(0, session.jar().len());
+        let members = login.fetch(&format!("{}/members", base)).unwrap();
+
+        assert_eq!("members", members.body);
+        assert_eq!(1, session.jar().len());
+        server.join().unwrap();
+    }
+}
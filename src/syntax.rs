@@ -0,0 +1,604 @@
+//! Lexer and grammar of the path DSL
+//!
+//! ```text
+//! path     = step { "->" step }
+//! step     = command | "{" command { "," command } "}"
+//! command  = name "(" [ argument { "," argument } ] ")"
+//! argument = name ":" value
+//! value    = quoted | raw
+//! ```
+//! A quoted value is delimited by `"` and supports the `\"`, `\\`, `\n` and `\t` escapes. Raw
+//! values run until the next `,` or `)` outside of brackets and quotes, and are kept verbatim, so
+//! most selectors do not need quoting. Names are case insensitive, values preserve their case.
+//! A block groups commands into a single step.
+
+/// Byte offsets of a part of the source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Step {
+    Command(Command),
+    /// Commands grouped between braces.
+    Block(Vec<Command>, Span),
+}
+
+impl Step {
+    /// The first command of the step.
+    pub fn first(&self) -> &Command {
+        match self {
+            Step::Command(command) => command,
+            Step::Block(commands, _) => &commands[0],
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Step::Command(command) => command.span,
+            Step::Block(_, span) => *span,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Command {
+    /// Upper case name of the command.
+    pub name: String,
+    pub name_span: Span,
+    pub arguments: Vec<Argument>,
+    /// The whole command including its arguments.
+    pub span: Span,
+}
+
+impl Command {
+    /// The argument of the name (case insensitive).
+    pub fn argument(&self, name: &str) -> Option<&Argument> {
+        self.arguments
+            .iter()
+            .find(|argument| argument.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Every argument of the name, when it might be repeated.
+    pub fn arguments_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Argument> {
+        self.arguments
+            .iter()
+            .filter(move |argument| argument.name.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Argument {
+    /// Upper case name of the argument.
+    pub name: String,
+    pub name_span: Span,
+    pub value: Value,
+}
+
+#[derive(Clone, Debug)]
+pub struct Value {
+    /// The unescaped content of a quoted value, the trimmed text of a raw one.
+    pub text: String,
+    pub quoted: bool,
+    pub span: Span,
+}
+
+impl Value {
+    /// Whether the value is the given keyword (case insensitive).
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        !self.quoted && self.text.eq_ignore_ascii_case(keyword)
+    }
+
+    /// Splits a value like `ALL(-)` into the upper case name and the raw text between the
+    /// parentheses. A quoted inner text is unescaped.
+    pub fn call(&self) -> Option<(String, String)> {
+        if self.quoted || !self.text.ends_with(')') {
+            return None;
+        }
+        let open = self.text.find('(')?;
+        let name = self.text[..open].trim();
+        if name.is_empty() || !name.chars().all(is_name_char) {
+            return None;
+        }
+        let inner = &self.text[open + 1..self.text.len() - 1];
+        let trimmed = inner.trim();
+        let inner = if trimmed.len() >= 2 && trimmed.starts_with('"') && trimmed.ends_with('"') {
+            unescape(&trimmed[1..trimmed.len() - 1])
+        } else {
+            String::from(inner)
+        };
+
+        Some((name.to_uppercase(), inner))
+    }
+
+    /// Splits a value like `REPLACE("a, b", c)` into the upper case name and the comma separated
+    /// values between the parentheses, that might be quoted.
+    pub fn call_values(&self) -> Option<(String, Vec<Value>)> {
+        let (name, _) = self.call()?;
+        let open = self.text.find('(')?;
+        let inner = &self.text[open + 1..self.text.len() - 1];
+        let offset = self.span.start + open + 1;
+        let mut scanner = Scanner::new(inner);
+        let mut values = Vec::new();
+
+        scanner.skip_whitespace();
+        while !scanner.is_at_end() {
+            let value = match scanner.peek() {
+                Some('"') => scanner.quoted(),
+                _ => scanner.raw(),
+            };
+            let value = value.ok()?;
+            values.push(Value {
+                span: Span::new(value.span.start + offset, value.span.end + offset),
+                ..value
+            });
+            scanner.skip_whitespace();
+            match scanner.bump() {
+                Some(',') => scanner.skip_whitespace(),
+                None => break,
+                Some(_) => return None,
+            }
+        }
+        Some((name, values))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SyntaxError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl SyntaxError {
+    fn new(message: &str, span: Span) -> SyntaxError {
+        SyntaxError {
+            message: String::from(message),
+            span,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, SyntaxError>;
+
+/// Parses the steps of a path. Steps with syntax errors are left out, the parsing goes on with
+/// the next step, so every error of the source is reported.
+pub fn parse(source: &str) -> (Vec<Step>, Vec<SyntaxError>) {
+    let mut scanner = Scanner::new(source);
+    let mut steps = Vec::new();
+    let mut errors = Vec::new();
+
+    loop {
+        match scanner.step() {
+            Ok(step) => {
+                steps.push(step);
+                scanner.skip_whitespace();
+                if scanner.is_at_end() {
+                    break;
+                }
+                if scanner.eat_arrow() {
+                    continue;
+                }
+                errors.push(scanner.error_here("Expected `->` between commands"));
+            }
+            Err(error) => errors.push(error),
+        }
+        if !scanner.skip_step() {
+            break;
+        }
+    }
+
+    (steps, errors)
+}
+
+/// Formats a value, that parses back to the text. It is quoted only when a raw value would not.
+pub fn format_value(text: &str) -> String {
+    let mut scanner = Scanner::new(text);
+    let raw = !text.starts_with(|c: char| c.is_whitespace() || c == '"');
+    match scanner.raw() {
+        Ok(ref value) if raw && scanner.is_at_end() && value.text == text => String::from(text),
+        _ => quote(text),
+    }
+}
+
+/// Formats a value like `ALL(-)`, that is split back to the name and the inner text by
+/// [`Value::call`].
+pub fn format_call(name: &str, inner: &str) -> String {
+    let call = format!("{}({})", name, inner);
+    let value = Value {
+        text: call.clone(),
+        quoted: false,
+        span: Span::new(0, call.len()),
+    };
+    match (format_value(&call) == call, value.call()) {
+        (true, Some((_, ref parsed))) if parsed == inner => call,
+        _ => format!("{}({})", name, quote(inner)),
+    }
+}
+
+/// Quotes and escapes the text.
+pub fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some('t') => unescaped.push('\t'),
+                Some(escaped) => unescaped.push(escaped),
+                None => unescaped.push('\\'),
+            },
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+struct Scanner<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(source: &'a str) -> Scanner<'a> {
+        Scanner {
+            source,
+            position: 0,
+        }
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.position >= self.source.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn eat_arrow(&mut self) -> bool {
+        if self.source[self.position..].starts_with("->") {
+            self.position += 2;
+            return true;
+        }
+        false
+    }
+
+    /// Skips to the next command after a syntax error. Returns false, when there is none.
+    fn skip_step(&mut self) -> bool {
+        let mut quoted = false;
+        while let Some(c) = self.peek() {
+            if !quoted && self.eat_arrow() {
+                return true;
+            }
+            match c {
+                '\\' if quoted => {
+                    self.bump();
+                }
+                '"' => quoted = !quoted,
+                _ => (),
+            }
+            self.bump();
+        }
+        false
+    }
+
+    fn error_here(&self, message: &str) -> SyntaxError {
+        let end = self.position + self.peek().map_or(0, char::len_utf8);
+        SyntaxError::new(message, Span::new(self.position, end))
+    }
+
+    fn name(&mut self) -> (String, Span) {
+        let start = self.position;
+        while self.peek().map_or(false, is_name_char) {
+            self.bump();
+        }
+        (
+            self.source[start..self.position].to_uppercase(),
+            Span::new(start, self.position),
+        )
+    }
+
+    fn step(&mut self) -> Result<Step> {
+        self.skip_whitespace();
+        if self.peek() != Some('{') {
+            return Ok(Step::Command(self.command()?));
+        }
+        let start = self.position;
+        self.bump();
+
+        let mut commands = Vec::new();
+        loop {
+            commands.push(self.command()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                Some('}') => {
+                    self.bump();
+                    break;
+                }
+                _ => return Err(self.error_here("Expected `,` or `}` after the command")),
+            }
+        }
+
+        Ok(Step::Block(commands, Span::new(start, self.position)))
+    }
+
+    fn command(&mut self) -> Result<Command> {
+        self.skip_whitespace();
+        let start = self.position;
+        let (name, name_span) = self.name();
+        if name.is_empty() {
+            return Err(self.error_here("Expected a command name"));
+        }
+        self.skip_whitespace();
+        if self.peek() != Some('(') {
+            return Err(self.error_here("Expected `(` after the command name"));
+        }
+        self.bump();
+
+        let mut arguments = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(')') {
+            self.bump();
+        } else {
+            loop {
+                arguments.push(self.argument()?);
+                match self.peek() {
+                    Some(',') => {
+                        self.bump();
+                    }
+                    Some(')') => {
+                        self.bump();
+                        break;
+                    }
+                    _ => return Err(self.error_here("Expected `,` or `)` after the value")),
+                }
+            }
+        }
+
+        Ok(Command {
+            name,
+            name_span,
+            arguments,
+            span: Span::new(start, self.position),
+        })
+    }
+
+    fn argument(&mut self) -> Result<Argument> {
+        self.skip_whitespace();
+        let (name, name_span) = self.name();
+        if name.is_empty() {
+            return Err(self.error_here("Expected an argument name"));
+        }
+        self.skip_whitespace();
+        if self.peek() != Some(':') {
+            return Err(self.error_here("Expected `:` after the argument name"));
+        }
+        self.bump();
+        self.skip_whitespace();
+
+        let value = match self.peek() {
+            Some('"') => self.quoted()?,
+            _ => self.raw()?,
+        };
+        self.skip_whitespace();
+
+        Ok(Argument {
+            name,
+            name_span,
+            value,
+        })
+    }
+
+    fn quoted(&mut self) -> Result<Value> {
+        let start = self.position;
+        self.bump();
+        loop {
+            match self.bump() {
+                Some('"') => break,
+                Some('\\') => {
+                    self.bump();
+                }
+                Some(_) => (),
+                None => {
+                    return Err(SyntaxError::new(
+                        "Unterminated string",
+                        Span::new(start, self.position),
+                    ));
+                }
+            }
+        }
+
+        Ok(Value {
+            text: unescape(&self.source[start + 1..self.position - 1]),
+            quoted: true,
+            span: Span::new(start, self.position),
+        })
+    }
+
+    fn raw(&mut self) -> Result<Value> {
+        let start = self.position;
+        let mut brackets: Vec<char> = Vec::new();
+        let mut quote: Option<char> = None;
+
+        while let Some(c) = self.peek() {
+            match (c, quote) {
+                ('\\', _) => {
+                    self.bump();
+                }
+                (_, Some(q)) if c == q => quote = None,
+                (_, Some(_)) => (),
+                ('"', None) | ('\'', None) => quote = Some(c),
+                ('(', None) => brackets.push(')'),
+                ('[', None) => brackets.push(']'),
+                (')', None) | (']', None) if brackets.last() == Some(&c) => {
+                    brackets.pop();
+                }
+                (',', None) | (')', None) if brackets.is_empty() => break,
+                ('-', None)
+                    if brackets.is_empty() && self.source[self.position..].starts_with("->") =>
+                {
+                    break
+                }
+                _ => (),
+            }
+            self.bump();
+        }
+
+        if quote.is_some() || !brackets.is_empty() {
+            return Err(SyntaxError::new(
+                "Unclosed bracket or quote in the value",
+                Span::new(start, self.position),
+            ));
+        }
+        let text = self.source[start..self.position].trim_end();
+        if text.is_empty() {
+            return Err(self.error_here("Missing value"));
+        }
+
+        Ok(Value {
+            text: String::from(text),
+            quoted: false,
+            span: Span::new(start, start + text.len()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        let (steps, errors) =
+            parse("START(SELECTOR: ul) -> find(selector: a, Select: ALL(-), LOC: ATTR(href))");
+        let commands: Vec<&Command> = steps.iter().map(Step::first).collect();
+
+        assert!(errors.is_empty());
+        assert_eq!(2, commands.len());
+        assert_eq!("FIND", commands[1].name);
+        assert_eq!("a", commands[1].argument("SELECTOR").unwrap().value.text);
+        let select = &commands[1].argument("select").unwrap().value;
+        assert_eq!(
+            Some((String::from("ALL"), String::from("-"))),
+            select.call()
+        );
+    }
+
+    #[test]
+    fn test_selectors_are_kept_verbatim() {
+        let (steps, errors) = parse(
+            r#"START(SELECTOR: div.Title > a[href^="http:"], SELECT: 0) -> FIND(SELECTOR: li:nth-child(2), LOC: TEXT)"#,
+        );
+        let commands: Vec<&Command> = steps.iter().map(Step::first).collect();
+
+        assert!(errors.is_empty());
+        let start = commands[0].argument("selector").unwrap();
+        assert_eq!(r#"div.Title > a[href^="http:"]"#, start.value.text);
+        let find = commands[1].argument("selector").unwrap();
+        assert_eq!("li:nth-child(2)", find.value.text);
+    }
+
+    #[test]
+    fn test_quoted_values_and_delimiters() {
+        let (steps, errors) =
+            parse(r#"FIND(SELECTOR: "h1, h2", SELECT: ALL(, ), NAME: "say \"hi\"")"#);
+        let commands: Vec<&Command> = steps.iter().map(Step::first).collect();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            "h1, h2",
+            commands[0].argument("selector").unwrap().value.text
+        );
+        let select = &commands[0].argument("select").unwrap().value;
+        assert_eq!(
+            Some(String::from(", ")),
+            select.call().map(|(_, inner)| inner)
+        );
+        assert_eq!(
+            r#"say "hi""#,
+            commands[0].argument("name").unwrap().value.text
+        );
+    }
+
+    #[test]
+    fn test_errors_are_collected_per_command() {
+        let source = r#"START(SELECTOR: ul -> DESCEND SELECTOR: a) -> FIND(SELECTOR: "a)"#;
+
+        let (steps, errors) = parse(source);
+
+        assert_eq!(0, steps.len());
+        assert_eq!(3, errors.len());
+        assert_eq!("Expected `,` or `)` after the value", errors[0].message);
+        assert_eq!("Expected `(` after the command name", errors[1].message);
+        assert_eq!("Unterminated string", errors[2].message);
+    }
+
+    #[test]
+    fn test_format_values() {
+        assert_eq!("li:nth-child(2)", format_value("li:nth-child(2)"));
+        assert_eq!(r#""h1, h2""#, format_value("h1, h2"));
+        assert_eq!(r#""say \"hi\"\n""#, format_value("say \"hi\"\n"));
+        assert_eq!("ALL(, )", format_call("ALL", ", "));
+        assert_eq!(r#"ALL(")")"#, format_call("ALL", ")"));
+        assert_eq!("ALL( )", format_call("ALL", " "));
+    }
+
+    #[test]
+    fn test_parse_blocks() {
+        let (steps, errors) = parse(
+            "START(SELECTOR: ul) -> { POPULATE(NAME: a, SELECTOR: a, LOC: TEXT), POPULATE(NAME: b, SELECTOR: b, LOC: TEXT) }",
+        );
+
+        assert!(errors.is_empty());
+        assert_eq!(2, steps.len());
+        match &steps[1] {
+            Step::Block(commands, span) => {
+                assert_eq!(2, commands.len());
+                assert_eq!("b", commands[1].argument("name").unwrap().value.text);
+                assert_eq!(Span::new(23, 111), *span);
+            }
+            _ => panic!("Failed"),
+        }
+    }
+}
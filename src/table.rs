@@ -0,0 +1,260 @@
+//! HTML tables as records
+//!
+//! The rows of a table are laid out on a grid, so a cell spanning several columns (`colspan`) or
+//! rows (`rowspan`) fills every position it covers. The header rows (of `thead`, or the leading
+//! rows of `th` cells) name the columns, every other row becomes a record keyed by the names.
+use crate::field::normalized_text;
+use scraper::ElementRef;
+use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::HashMap;
+
+/// Cells never span more positions than this (eg. on `rowspan="0"`, spanning every following row).
+const MAX_SPAN: usize = 1000;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+/// Extracts every row of a table into a record.
+pub struct Table {
+    /// Keeps the columns by their header text, every column is kept without any.
+    #[serde(default)]
+    pub columns: Vec<String>,
+    /// Renames the columns by their header text.
+    #[serde(default)]
+    pub rename: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug)]
+struct Cell {
+    text: String,
+    header: bool,
+}
+
+impl Table {
+    pub fn new() -> Table {
+        Table::default()
+    }
+
+    /// Keep the column with the header text.
+    pub fn column(&mut self, header: &str) -> &mut Self {
+        self.columns.push(String::from(header));
+        return self;
+    }
+
+    /// Name the values of the column with the header text differently.
+    pub fn rename(&mut self, header: &str, name: &str) -> &mut Self {
+        self.rename.insert(String::from(header), String::from(name));
+        return self;
+    }
+
+    /// Returns the constructed table
+    pub fn build(&self) -> Table {
+        self.clone()
+    }
+
+    /// The records of the element, when it is a table, or of the first table below it. A column
+    /// without header text is named by its index.
+    pub fn extract(&self, element: &ElementRef) -> Vec<HashMap<String, String>> {
+        let table = match element.value().name() {
+            "table" => Some(*element),
+            _ => element
+                .descendants()
+                .filter_map(ElementRef::wrap)
+                .find(|descendant| descendant.value().name() == "table"),
+        };
+        let rows = match table {
+            Some(table) => rows(&table),
+            None => return Vec::new(),
+        };
+
+        let grid = layout(&rows);
+        let header_rows = match rows.iter().filter(|(_, in_head)| *in_head).count() {
+            0 => grid
+                .iter()
+                .take_while(|row| !row.is_empty() && row.iter().all(|cell| cell_is_header(cell)))
+                .count(),
+            count => count,
+        };
+        // Without header rows the first row names the columns
+        let header_rows = cmp::max(header_rows, 1);
+        let width = grid.iter().map(|row| row.len()).max().unwrap_or(0);
+        let headers: Vec<String> = (0..width)
+            .map(|column| header_text(&grid[..cmp::min(header_rows, grid.len())], column))
+            .collect();
+
+        grid.iter()
+            .skip(header_rows)
+            .map(|row| self.record(&headers, row))
+            .collect()
+    }
+
+    fn record(&self, headers: &[String], row: &[Option<Cell>]) -> HashMap<String, String> {
+        let mut record = HashMap::new();
+        for (column, header) in headers.iter().enumerate() {
+            if !self.columns.is_empty() && !self.columns.contains(header) {
+                continue;
+            }
+            let name = self.rename.get(header).unwrap_or(header);
+            let value = match row.get(column) {
+                Some(Some(cell)) => cell.text.clone(),
+                _ => String::new(),
+            };
+            record.entry(name.clone()).or_insert(value);
+        }
+        record
+    }
+}
+
+/// The rows of the table (but not of the tables nested in it) with whether they are in `thead`.
+fn rows<'b>(table: &ElementRef<'b>) -> Vec<(ElementRef<'b>, bool)> {
+    let mut rows = Vec::new();
+    for child in table.children().filter_map(ElementRef::wrap) {
+        match child.value().name() {
+            "tr" => rows.push((child, false)),
+            section @ "thead" | section @ "tbody" | section @ "tfoot" => rows.extend(
+                child
+                    .children()
+                    .filter_map(ElementRef::wrap)
+                    .filter(|row| row.value().name() == "tr")
+                    .map(|row| (row, section == "thead")),
+            ),
+            _ => (),
+        }
+    }
+    rows
+}
+
+/// Places the cells of the rows on a grid, a spanning cell is repeated at every position.
+fn layout(rows: &[(ElementRef, bool)]) -> Vec<Vec<Option<Cell>>> {
+    let mut grid: Vec<Vec<Option<Cell>>> = vec![Vec::new(); rows.len()];
+    for (r, (row, _)) in rows.iter().enumerate() {
+        let mut column = 0;
+        let cells = row
+            .children()
+            .filter_map(ElementRef::wrap)
+            .filter(|cell| cell.value().name() == "td" || cell.value().name() == "th");
+        for element in cells {
+            while grid[r].get(column).map_or(false, Option::is_some) {
+                column += 1;
+            }
+            let cell = Cell {
+                text: normalized_text(&element),
+                header: element.value().name() == "th",
+            };
+            let colspan = span(&element, "colspan");
+            let rowspan = cmp::min(span(&element, "rowspan"), rows.len() - r);
+            for spanned in &mut grid[r..r + rowspan] {
+                if spanned.len() < column + colspan {
+                    spanned.resize(column + colspan, None);
+                }
+                for position in &mut spanned[column..column + colspan] {
+                    *position = Some(cell.clone());
+                }
+            }
+            column += colspan;
+        }
+    }
+    grid
+}
+
+/// The span of a cell, a missing or invalid span is a single position. `rowspan="0"` spans every
+/// following row, `colspan="0"` a single column.
+fn span(cell: &ElementRef, attribute: &str) -> usize {
+    match cell.value().attr(attribute).map(|span| span.trim().parse()) {
+        Some(Ok(0)) if attribute == "rowspan" => MAX_SPAN,
+        Some(Ok(0)) => 1,
+        Some(Ok(span)) => cmp::min(span, MAX_SPAN),
+        _ => 1,
+    }
+}
+
+fn cell_is_header(cell: &Option<Cell>) -> bool {
+    cell.as_ref().map_or(false, |cell| cell.header)
+}
+
+/// The texts of the stacked header cells above the column, a spanning cell counts once.
+fn header_text(header_rows: &[Vec<Option<Cell>>], column: usize) -> String {
+    let mut texts: Vec<&str> = Vec::new();
+    for row in header_rows {
+        if let Some(Some(cell)) = row.get(column) {
+            if !cell.text.is_empty() && texts.last() != Some(&cell.text.as_str()) {
+                texts.push(&cell.text);
+            }
+        }
+    }
+    match texts.is_empty() {
+        true => column.to_string(),
+        false => texts.join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scraper::Html;
+
+    #[test]
+    fn test_records_of_table_with_spans() {
+        let html = Html::parse_fragment(
+            r#"<table>
+            <thead>
+                <tr><th rowspan="2">Name</th><th colspan="2">Price</th></tr>
+                <tr><th>Net</th><th>Gross</th></tr>
+            </thead>
+            <tbody>
+                <tr><td>Lamp</td><td>10</td><td>12</td></tr>
+                <tr><td rowspan="2">Chair</td><td colspan="2">free</td></tr>
+                <tr><td>5</td></tr>
+            </tbody>
+            </table>"#,
+        );
+
+        let records = Table::new().extract(&html.root_element());
+
+        assert_eq!(3, records.len());
+        assert_eq!("Lamp", records[0]["Name"]);
+        assert_eq!("12", records[0]["Price Gross"]);
+        assert_eq!("free", records[1]["Price Net"]);
+        assert_eq!("free", records[1]["Price Gross"]);
+        assert_eq!("Chair", records[2]["Name"]);
+        assert_eq!("5", records[2]["Price Net"]);
+        assert_eq!("", records[2]["Price Gross"]);
+    }
+
+    #[test]
+    fn test_selected_and_renamed_columns() {
+        let html = Html::parse_fragment(
+            r#"<table><tr><td>Name</td><td>Unit price</td><td></td></tr>
+            <tr><td> Lamp </td><td>12 EUR</td><td>x</td></tr></table>"#,
+        );
+
+        let records = Table::new()
+            .column("Unit price")
+            .column("2")
+            .rename("Unit price", "price")
+            .build()
+            .extract(&html.root_element());
+
+        assert_eq!(1, records.len());
+        assert_eq!(2, records[0].len());
+        assert_eq!("12 EUR", records[0]["price"]);
+        assert_eq!("x", records[0]["2"]);
+    }
+
+    #[test]
+    fn test_zero_spans() {
+        let html = Html::parse_fragment(
+            r#"<table><tr><th>Name</th><th>Kind</th><th>Price</th></tr>
+            <tr><td rowspan="0">Lamp</td><td colspan="0">desk</td><td>12</td></tr>
+            <tr><td>floor</td><td>20</td></tr></table>"#,
+        );
+
+        let records = Table::new().extract(&html.root_element());
+
+        assert_eq!(2, records.len());
+        assert_eq!("desk", records[0]["Kind"]);
+        assert_eq!("12", records[0]["Price"]);
+        assert_eq!("Lamp", records[1]["Name"]);
+        assert_eq!("floor", records[1]["Kind"]);
+        assert_eq!("20", records[1]["Price"]);
+    }
+}
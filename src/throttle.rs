@@ -0,0 +1,302 @@
+//! Per-host rate limiting of requests
+//!
+use crate::error::Result;
+use crate::fetch::Fetcher;
+use crate::fetch::Response;
+use crate::retry::retry_after;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+const TOO_MANY_REQUESTS: u16 = 429;
+const SERVICE_UNAVAILABLE: u16 = 503;
+/// The interval of a slowed down host never grows above this.
+const MAX_INTERVAL: Duration = Duration::from_secs(60);
+const MIN_PENALTY: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+/// Limits the requests sent to a single host.
+pub struct RateLimit {
+    pub requests_per_second: f64,
+    /// The number of requests to a host, that might be in flight at the same time.
+    pub max_concurrency: usize,
+}
+
+impl Default for RateLimit {
+    fn default() -> RateLimit {
+        RateLimit {
+            requests_per_second: 2.0,
+            max_concurrency: 4,
+        }
+    }
+}
+
+impl RateLimit {
+    pub fn new(requests_per_second: f64, max_concurrency: usize) -> RateLimit {
+        RateLimit {
+            requests_per_second,
+            max_concurrency,
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        if self.requests_per_second <= 0.0 {
+            return Duration::from_secs(0);
+        }
+        Duration::from_micros((1_000_000.0 / self.requests_per_second) as u64)
+    }
+}
+
+struct HostState {
+    next_slot: Instant,
+    interval: Duration,
+    in_flight: usize,
+}
+
+/// A request in flight to a host. The slot is freed on drop, even when the inner fetcher panics.
+struct Slot<'a> {
+    fetcher: &'a ThrottledFetcher,
+    host: String,
+}
+
+impl<'a> Drop for Slot<'a> {
+    fn drop(&mut self) {
+        let mut hosts = self.fetcher.hosts();
+        if let Some(state) = hosts.get_mut(&self.host) {
+            state.in_flight -= 1;
+        }
+        self.fetcher.released.notify_all();
+    }
+}
+
+/// Spaces out the requests of the inner fetcher per host according to a [`RateLimit`].
+///
+/// A `429 Too Many Requests` (or a `503` with `Retry-After`) response slows the host down: its
+/// interval is doubled and no request is sent before the `Retry-After` delay (at most a minute)
+/// elapses. Every successful response moves the interval back towards the configured rate.
+pub struct ThrottledFetcher {
+    inner: Arc<dyn Fetcher>,
+    limit: RateLimit,
+    hosts: Mutex<HashMap<String, HostState>>,
+    released: Condvar,
+}
+
+impl ThrottledFetcher {
+    pub fn new(inner: Arc<dyn Fetcher>, limit: RateLimit) -> ThrottledFetcher {
+        ThrottledFetcher {
+            inner,
+            limit,
+            hosts: Mutex::new(HashMap::new()),
+            released: Condvar::new(),
+        }
+    }
+
+    /// The current interval between two requests to the host of the url.
+    pub fn interval(&self, url: &str) -> Duration {
+        let hosts = self.hosts();
+        hosts
+            .get(&host_of(url))
+            .map_or(self.limit.interval(), |state| state.interval)
+    }
+
+    /// The state of the hosts. A panic while holding the lock leaves no partial update behind, so
+    /// a poisoned lock is still usable.
+    fn hosts(&self) -> MutexGuard<'_, HashMap<String, HostState>> {
+        self.hosts
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Waits for a free slot of the host and returns it with the time to sleep before sending.
+    fn acquire(&self, host: &str) -> (Slot<'_>, Duration) {
+        let max_concurrency = cmp::max(self.limit.max_concurrency, 1);
+        let mut hosts = self.hosts();
+        while hosts
+            .get(host)
+            .map_or(false, |state| state.in_flight >= max_concurrency)
+        {
+            hosts = self
+                .released
+                .wait(hosts)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+
+        let now = Instant::now();
+        let base_interval = self.limit.interval();
+        let state = hosts.entry(String::from(host)).or_insert(HostState {
+            next_slot: now,
+            interval: base_interval,
+            in_flight: 0,
+        });
+        let start = cmp::max(state.next_slot, now);
+        state.next_slot = start + state.interval;
+        state.in_flight += 1;
+
+        let slot = Slot {
+            fetcher: self,
+            host: String::from(host),
+        };
+        (slot, start - now)
+    }
+
+    /// Slows the host down or speeds it up according to the outcome of a request.
+    fn adjust(&self, host: &str, outcome: &Result<Response>) {
+        let mut hosts = self.hosts();
+        let base_interval = self.limit.interval();
+        if let Some(state) = hosts.get_mut(host) {
+            match outcome {
+                Ok(response) if is_throttled(response) => {
+                    let slowed = cmp::max(state.interval * 2, MIN_PENALTY);
+                    state.interval = cmp::min(slowed, cmp::max(MAX_INTERVAL, base_interval));
+                    let wait = retry_after(response).map_or(state.interval, |retry_after| {
+                        cmp::max(cmp::min(retry_after, MAX_INTERVAL), state.interval)
+                    });
+                    state.next_slot = cmp::max(state.next_slot, Instant::now() + wait);
+                }
+                Ok(_) => {
+                    state.interval = cmp::max(base_interval, state.interval * 9 / 10);
+                }
+                Err(_) => (),
+            }
+        }
+    }
+}
+
+impl Fetcher for ThrottledFetcher {
+    fn fetch(&self, url: &str) -> Result<Response> {
+        let host = host_of(url);
+        let (_slot, wait) = self.acquire(&host);
+        thread::sleep(wait);
+
+        let outcome = self.inner.fetch(url);
+        self.adjust(&host, &outcome);
+        outcome
+    }
+}
+
+/// Whether the server asks to slow down with the response.
+pub(crate) fn is_throttled(response: &Response) -> bool {
+    response.status == TOO_MANY_REQUESTS
+        || (response.status == SERVICE_UNAVAILABLE && response.header("Retry-After").is_some())
+}
+
+/// The host of the url, or an empty string, when the url has none.
+pub fn host_of(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_lowercase()))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic;
+    use std::panic::AssertUnwindSafe;
+
+    struct StatusFetcher {
+        status: u16,
+        retry_after: Option<String>,
+    }
+
+    impl Fetcher for StatusFetcher {
+        fn fetch(&self, url: &str) -> Result<Response> {
+            let mut response = Response::new(url, "");
+            response.status = self.status;
+            if let Some(retry_after) = &self.retry_after {
+                response
+                    .headers
+                    .push((String::from("Retry-After"), retry_after.clone()));
+            }
+            Ok(response)
+        }
+    }
+
+    #[test]
+    fn test_requests_are_spaced_per_host() {
+        let inner = Arc::new(StatusFetcher {
+            status: 200,
+            retry_after: None,
+        });
+        let fetcher = ThrottledFetcher::new(inner, RateLimit::new(20.0, 1));
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            fetcher.fetch("http://test/page").unwrap();
+        }
+        fetcher.fetch("http://other/page").unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_slow_down_on_too_many_requests() {
+        let inner = Arc::new(StatusFetcher {
+            status: 429,
+            retry_after: Some(String::from("0")),
+        });
+        let fetcher = ThrottledFetcher::new(inner, RateLimit::new(1000.0, 1));
+
+        fetcher.fetch("http://test/page").unwrap();
+        fetcher.fetch("http://test/page").unwrap();
+
+        assert_eq!(
+            Duration::from_millis(200),
+            fetcher.interval("http://test/page")
+        );
+        assert_eq!(
+            Duration::from_millis(1),
+            fetcher.interval("http://other/page")
+        );
+    }
+
+    #[test]
+    fn test_retry_after_is_capped() {
+        let inner = Arc::new(StatusFetcher {
+            status: 429,
+            retry_after: Some(String::from("86400")),
+        });
+        let fetcher = ThrottledFetcher::new(inner, RateLimit::new(1000.0, 1));
+
+        fetcher.fetch("http://test/page").unwrap();
+        let next_slot = fetcher.hosts()["test"].next_slot;
+
+        assert!(next_slot <= Instant::now() + MAX_INTERVAL);
+    }
+
+    struct PanickingFetcher;
+
+    impl Fetcher for PanickingFetcher {
+        fn fetch(&self, _url: &str) -> Result<Response> {
+            panic!("Fetcher panicked")
+        }
+    }
+
+    #[test]
+    fn test_slot_is_released_on_panic() {
+        let fetcher = ThrottledFetcher::new(Arc::new(PanickingFetcher), RateLimit::new(0.0, 1));
+
+        let fetched = panic::catch_unwind(AssertUnwindSafe(|| fetcher.fetch("http://test/page")));
+
+        assert!(fetched.is_err());
+        assert_eq!(0, fetcher.hosts()["test"].in_flight);
+    }
+
+    #[test]
+    fn test_host_of_url() {
+        assert_eq!(
+            "news.ycombinator.com",
+            host_of("https://News.ycombinator.com/item")
+        );
+        assert_eq!("", host_of("not an url"));
+    }
+}
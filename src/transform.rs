@@ -0,0 +1,229 @@
+//! Cleanup of the extracted values
+//!
+//! The transforms of a field are applied in order on every value extracted from the selected
+//! elements, before several values are joined.
+use crate::error::DebrisError;
+use crate::error::Result;
+use regex::Regex;
+use scraper::Html;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// Changes an extracted value. Negative indexes count from the end.
+pub enum Transform {
+    /// Remove the leading and trailing whitespaces.
+    Trim,
+    /// Replace every run of whitespaces by a single space and trim the value.
+    CollapseWhitespace,
+    Lowercase,
+    Uppercase,
+    /// The group of the first match of the regular expression, empty without a match.
+    Capture(String, usize),
+    /// Replace every match of the regular expression. The replacement might refer to the groups
+    /// (eg. `$1`).
+    Replace(String, String),
+    /// The nth part of the value split by the separator, empty without it.
+    Split(String, i32),
+    Normalize(Normalization),
+    /// Decode the HTML character references (eg. `&amp;`).
+    DecodeEntities,
+    Prefix(String),
+    Suffix(String),
+    /// The characters from the start index up to the end index (exclusive), or up to the end of
+    /// the value without an end.
+    Substring(i32, Option<i32>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// Unicode normalization forms.
+pub enum Normalization {
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+}
+
+impl Transform {
+    /// Compiles the regular expression of the transform, if it has one.
+    pub fn regex(&self) -> Result<Option<Regex>> {
+        match self {
+            Transform::Capture(pattern, _) | Transform::Replace(pattern, _) => {
+                Regex::new(pattern).map(Some).map_err(|_| {
+                    DebrisError::Config(format!("Invalid regular expression: {}", pattern))
+                })
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn apply(&self, value: String, regex: Option<&Regex>) -> String {
+        match (self, regex) {
+            (Transform::Trim, _) => String::from(value.trim()),
+            (Transform::CollapseWhitespace, _) => {
+                value.split_whitespace().collect::<Vec<_>>().join(" ")
+            }
+            (Transform::Lowercase, _) => value.to_lowercase(),
+            (Transform::Uppercase, _) => value.to_uppercase(),
+            (Transform::Capture(_, group), Some(regex)) => regex
+                .captures(&value)
+                .and_then(|captures| captures.get(*group))
+                .map_or(String::new(), |matched| String::from(matched.as_str())),
+            (Transform::Replace(_, replacement), Some(regex)) => {
+                regex.replace_all(&value, replacement.as_str()).into_owned()
+            }
+            (Transform::Split(separator, n), _) => {
+                let parts: Vec<&str> = value.split(separator.as_str()).collect();
+                match index(*n, parts.len()) {
+                    Some(i) if i < parts.len() => String::from(parts[i]),
+                    _ => String::new(),
+                }
+            }
+            (Transform::Normalize(form), _) => match form {
+                Normalization::Nfc => value.nfc().collect(),
+                Normalization::Nfd => value.nfd().collect(),
+                Normalization::Nfkc => value.nfkc().collect(),
+                Normalization::Nfkd => value.nfkd().collect(),
+            },
+            (Transform::DecodeEntities, _) => decode_entities(&value),
+            (Transform::Prefix(prefix), _) => format!("{}{}", prefix, value),
+            (Transform::Suffix(suffix), _) => format!("{}{}", value, suffix),
+            (Transform::Substring(start, end), _) => {
+                let length = value.chars().count();
+                let start = index(*start, length).unwrap_or(0);
+                let end = end.map_or(Some(length), |end| index(end, length));
+                match end {
+                    Some(end) if start < end => {
+                        value.chars().skip(start).take(end - start).collect()
+                    }
+                    _ => String::new(),
+                }
+            }
+            _ => value,
+        }
+    }
+}
+
+/// Applies the transforms in order on every value.
+pub fn apply(transforms: &[Transform], values: Vec<String>) -> Result<Vec<String>> {
+    if transforms.is_empty() {
+        return Ok(values);
+    }
+    let mut compiled = Vec::new();
+    for transform in transforms {
+        compiled.push((transform, transform.regex()?));
+    }
+
+    Ok(values
+        .into_iter()
+        .map(|value| {
+            compiled.iter().fold(value, |value, (transform, regex)| {
+                transform.apply(value, regex.as_ref())
+            })
+        })
+        .collect())
+}
+
+/// The position of the index in a sequence of the length, a negative index before the start is
+/// missing.
+fn index(n: i32, length: usize) -> Option<usize> {
+    match n < 0 {
+        true => length.checked_sub(n.abs() as usize),
+        false => Some(n as usize),
+    }
+}
+
+/// The HTML parser decodes the references, the value is escaped, so its tags are kept as text.
+fn decode_entities(value: &str) -> String {
+    let fragment = Html::parse_fragment(&value.replace('<', "&lt;"));
+    fragment.root_element().text().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transformed(transforms: &[Transform], value: &str) -> String {
+        apply(transforms, vec![String::from(value)])
+            .unwrap()
+            .remove(0)
+    }
+
+    #[test]
+    fn test_text_cleanup() {
+        let value = "  Hello \n  World  ";
+
+        assert_eq!("Hello \n  World", transformed(&[Transform::Trim], value));
+        assert_eq!(
+            "hello world",
+            transformed(
+                &[Transform::CollapseWhitespace, Transform::Lowercase],
+                value
+            )
+        );
+        assert_eq!(
+            "<b>Tom & Jerry</b> é",
+            transformed(
+                &[Transform::DecodeEntities],
+                "<b>Tom &amp; Jerry</b> &eacute;"
+            )
+        );
+        assert_eq!(
+            "\u{e9}",
+            transformed(&[Transform::Normalize(Normalization::Nfc)], "e\u{301}")
+        );
+        assert_eq!(
+            "[EUR]",
+            transformed(
+                &[
+                    Transform::Uppercase,
+                    Transform::Prefix(String::from("[")),
+                    Transform::Suffix(String::from("]"))
+                ],
+                "eur"
+            )
+        );
+    }
+
+    #[test]
+    fn test_regex_and_parts() {
+        let price = "Price: 1,299 EUR";
+
+        assert_eq!(
+            "1,299",
+            transformed(&[Transform::Capture(String::from(r"(\d[\d,]*)"), 1)], price)
+        );
+        assert_eq!(
+            "1299",
+            transformed(
+                &[
+                    Transform::Capture(String::from(r"[\d,]+"), 0),
+                    Transform::Replace(String::from(","), String::new())
+                ],
+                price
+            )
+        );
+        assert_eq!(
+            "EUR",
+            transformed(&[Transform::Split(String::from(" "), -1)], price)
+        );
+        assert_eq!(
+            "",
+            transformed(&[Transform::Split(String::from(" "), 5)], price)
+        );
+        assert_eq!(
+            "Price",
+            transformed(&[Transform::Substring(0, Some(5))], price)
+        );
+        assert_eq!("EUR", transformed(&[Transform::Substring(-3, None)], price));
+        matches::assert_matches!(
+            apply(
+                &[Transform::Replace(String::from("("), String::new())],
+                Vec::new()
+            ),
+            Err(DebrisError::Config(_))
+        );
+    }
+}
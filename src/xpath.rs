@@ -0,0 +1,363 @@
+//! XPath 1.0 selection on parsed HTML documents
+//!
+//! The elements of the HTML document are mirrored into a [`sxd_document`] package, the XPath is
+//! evaluated there and the selected elements are mapped back to the elements of the HTML tree by
+//! their position among the element children of their parents.
+//!
+//! Expressions are compiled once per thread. The mirror of a document is kept while a
+//! [`DocumentScope`] of it is alive, otherwise every selection mirrors the document again.
+use crate::error::DebrisError;
+use crate::error::Result;
+use ego_tree::NodeId;
+use ego_tree::NodeRef;
+use ego_tree::Tree;
+use scraper::ElementRef;
+use scraper::Html;
+use scraper::Node;
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::iter;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use sxd_document::dom;
+use sxd_document::Package;
+use sxd_xpath::nodeset::Node as XPathNode;
+use sxd_xpath::Context;
+use sxd_xpath::Factory;
+use sxd_xpath::Value;
+use sxd_xpath::XPath;
+
+thread_local! {
+    static COMPILED: RefCell<HashMap<String, Rc<XPath>>> = RefCell::new(HashMap::new());
+    /// The mirrors of the documents in scope by the address of their tree, built on first use.
+    static MIRRORS: RefCell<HashMap<usize, Option<Rc<Package>>>> = RefCell::new(HashMap::new());
+}
+
+/// Keeps the mirror of the document for the XPath selections on it, until the scope is dropped.
+pub struct DocumentScope<'a> {
+    key: usize,
+    document: PhantomData<&'a Html>,
+}
+
+impl<'a> DocumentScope<'a> {
+    pub fn new(html: &'a Html) -> DocumentScope<'a> {
+        let key = tree_key(&html.tree);
+        MIRRORS.with(|mirrors| {
+            mirrors.borrow_mut().entry(key).or_insert(None);
+        });
+        DocumentScope {
+            key,
+            document: PhantomData,
+        }
+    }
+}
+
+impl<'a> Drop for DocumentScope<'a> {
+    fn drop(&mut self) {
+        MIRRORS.with(|mirrors| mirrors.borrow_mut().remove(&self.key));
+    }
+}
+
+/// Compiles an XPath expression.
+pub fn compile(expression: &str) -> Result<XPath> {
+    match Factory::new().build(expression) {
+        Ok(Some(xpath)) => Ok(xpath),
+        _ => Err(DebrisError::invalid_selector(expression)),
+    }
+}
+
+/// The elements selected by the expression in the context of the element, in document order.
+pub fn select<'b>(context: &ElementRef<'b>, expression: &str) -> Result<Vec<ElementRef<'b>>> {
+    let xpath = compiled(expression)?;
+    let tree = context.tree();
+    let package = mirror_of(tree);
+    let selected = evaluate(&xpath, expression, &package, tree, Some(*context))?;
+    Ok(selected
+        .into_iter()
+        .filter_map(|id| tree.get(id))
+        .filter_map(ElementRef::wrap)
+        .collect())
+}
+
+/// The first candidate matching the expression. A candidate matches like in an XSLT pattern: when
+/// the expression selects it in the context of one of its ancestors (eg. `td` matches every `td`
+/// element, `tr/td[1]` the first cell of every row).
+pub fn first_match<'b, I>(expression: &str, candidates: I) -> Result<Option<ElementRef<'b>>>
+where
+    I: Iterator<Item = ElementRef<'b>>,
+{
+    let xpath = compiled(expression)?;
+    let mut mirror = None;
+    let mut selections: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+
+    for candidate in candidates {
+        let package = mirror
+            .get_or_insert_with(|| mirror_of(candidate.tree()))
+            .clone();
+        for ancestor in iter::once(*candidate).chain(candidate.ancestors()) {
+            let selected = match selections.entry(ancestor.id()) {
+                Entry::Occupied(selected) => selected.into_mut(),
+                Entry::Vacant(entry) => {
+                    let context = ElementRef::wrap(ancestor);
+                    entry.insert(evaluate(
+                        &xpath,
+                        expression,
+                        &package,
+                        candidate.tree(),
+                        context,
+                    )?)
+                }
+            };
+            if selected.contains(&candidate.id()) {
+                return Ok(Some(candidate));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn compiled(expression: &str) -> Result<Rc<XPath>> {
+    if let Some(xpath) = COMPILED.with(|compiled| compiled.borrow().get(expression).cloned()) {
+        return Ok(xpath);
+    }
+    let xpath = Rc::new(compile(expression)?);
+    COMPILED.with(|compiled| {
+        compiled
+            .borrow_mut()
+            .insert(String::from(expression), xpath.clone())
+    });
+    Ok(xpath)
+}
+
+/// The mirror of the tree, kept for the next selections when the document is in scope.
+fn mirror_of(tree: &Tree<Node>) -> Rc<Package> {
+    let key = tree_key(tree);
+    let cached = MIRRORS.with(|mirrors| match mirrors.borrow().get(&key) {
+        Some(Some(package)) => Some(Some(package.clone())),
+        Some(None) => Some(None),
+        None => None,
+    });
+    match cached {
+        Some(Some(package)) => package,
+        Some(None) => {
+            let package = Rc::new(mirror(tree));
+            MIRRORS.with(|mirrors| {
+                mirrors.borrow_mut().insert(key, Some(package.clone()));
+            });
+            package
+        }
+        None => Rc::new(mirror(tree)),
+    }
+}
+
+fn tree_key(tree: &Tree<Node>) -> usize {
+    tree as *const Tree<Node> as usize
+}
+
+/// The ids of the elements of the tree selected by the expression in document order. Without an
+/// element, the expression is evaluated on the root of the document.
+fn evaluate(
+    xpath: &XPath,
+    expression: &str,
+    package: &Package,
+    tree: &Tree<Node>,
+    context: Option<ElementRef>,
+) -> Result<Vec<NodeId>> {
+    let document = package.as_document();
+    let node: XPathNode = match context.and_then(|element| mirrored(document, *element)) {
+        Some(element) => element.into(),
+        None => document.root().into(),
+    };
+
+    let nodeset = match xpath.evaluate(&Context::new(), node) {
+        Ok(Value::Nodeset(nodeset)) => nodeset,
+        _ => {
+            return Err(DebrisError::Selector(format!(
+                "XPath does not select elements: {}",
+                expression
+            )));
+        }
+    };
+    Ok(nodeset
+        .document_order()
+        .into_iter()
+        .filter_map(|node| node.element())
+        .filter_map(|element| original(tree.root(), element))
+        .collect())
+}
+
+/// Mirrors the elements and their texts of the tree into a new package.
+fn mirror(tree: &Tree<Node>) -> Package {
+    let package = Package::new();
+    {
+        let document = package.as_document();
+        for child in tree.root().children() {
+            append(document, child, None);
+        }
+    }
+    package
+}
+
+/// Appends the node to the parent (or the root of the document) with its descendants.
+fn append<'d>(document: dom::Document<'d>, node: NodeRef<Node>, parent: Option<dom::Element<'d>>) {
+    match node.value() {
+        Node::Element(html_element) => {
+            let element = document.create_element(html_element.name());
+            for (name, value) in html_element.attrs() {
+                element.set_attribute_value(name, value);
+            }
+            match parent {
+                Some(parent) => parent.append_child(element),
+                None => document.root().append_child(element),
+            }
+            for child in node.children() {
+                append(document, child, Some(element));
+            }
+        }
+        Node::Text(text) => {
+            if let Some(parent) = parent {
+                parent.append_child(document.create_text(text));
+            }
+        }
+        _ => (),
+    }
+}
+
+/// The mirrored element of the HTML node, found by the positions of its ancestors.
+fn mirrored<'d>(document: dom::Document<'d>, node: NodeRef<Node>) -> Option<dom::Element<'d>> {
+    let mut positions = Vec::new();
+    let mut current = node;
+    while let Some(parent) = current.parent() {
+        positions.push(
+            parent
+                .children()
+                .filter(|child| child.value().is_element())
+                .position(|child| child.id() == current.id())?,
+        );
+        current = parent;
+    }
+
+    let mut children: Vec<dom::Element> = document
+        .root()
+        .children()
+        .into_iter()
+        .filter_map(|child| child.element())
+        .collect();
+    let mut element = None;
+    for position in positions.into_iter().rev() {
+        let next = *children.get(position)?;
+        children = element_children(next);
+        element = Some(next);
+    }
+    element
+}
+
+/// The id of the HTML node mirrored into the element.
+fn original(root: NodeRef<Node>, element: dom::Element) -> Option<NodeId> {
+    let mut positions = Vec::new();
+    let mut current = element;
+    loop {
+        match current.parent()? {
+            dom::ParentOfChild::Element(parent) => {
+                positions.push(
+                    element_children(parent)
+                        .iter()
+                        .position(|e| *e == current)?,
+                );
+                current = parent;
+            }
+            dom::ParentOfChild::Root(document_root) => {
+                let siblings = document_root.children().into_iter();
+                positions.push(
+                    siblings
+                        .filter_map(|child| child.element())
+                        .position(|e| e == current)?,
+                );
+                break;
+            }
+        }
+    }
+
+    let mut node = root;
+    for position in positions.into_iter().rev() {
+        node = node
+            .children()
+            .filter(|child| child.value().is_element())
+            .nth(position)?;
+    }
+    Some(node.id())
+}
+
+fn element_children(element: dom::Element) -> Vec<dom::Element> {
+    element
+        .children()
+        .into_iter()
+        .filter_map(|child| child.element())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_by_xpath() {
+        let html = Html::parse_fragment(
+            r#"<table><tr><td>1</td><td><a href="/first">first</a></td></tr>
+            <tr><td>2</td><td><a href="/second">second</a></td></tr></table>"#,
+        );
+
+        let links = select(&html.root_element(), "//td[2]/a").unwrap();
+        let following = select(&links[0], "../preceding-sibling::td[text()='1']").unwrap();
+
+        assert_eq!(2, links.len());
+        assert_eq!(Some("/second"), links[1].value().attr("href"));
+        assert_eq!(1, following.len());
+        assert_eq!("td", following[0].value().name());
+    }
+
+    #[test]
+    fn test_mirror_is_kept_in_scope() {
+        let html = Html::parse_fragment("<p><b>1</b></p><p><b>2</b></p>");
+        let scope = DocumentScope::new(&html);
+
+        let first = select(&html.root_element(), "//p[1]/b").unwrap();
+        let second = select(&html.root_element(), "//p[2]/b").unwrap();
+        let kept = MIRRORS.with(|mirrors| mirrors.borrow()[&tree_key(&html.tree)].is_some());
+        drop(scope);
+
+        assert_eq!("1", first[0].inner_html());
+        assert_eq!("2", second[0].inner_html());
+        assert!(kept);
+        assert!(MIRRORS.with(|mirrors| mirrors.borrow().is_empty()));
+    }
+
+    #[test]
+    fn test_first_match_by_pattern() {
+        let html = Html::parse_fragment(
+            r#"<table><tr><th>Name</th><td class="a">1</td><td class="b">2</td></tr></table>"#,
+        );
+        let header = select(&html.root_element(), "//th").unwrap()[0];
+
+        let sibling = first_match(
+            "td[@class='b']",
+            header.next_siblings().filter_map(ElementRef::wrap),
+        );
+        let closest = first_match("table", header.ancestors().filter_map(ElementRef::wrap));
+
+        assert_eq!(Some("b"), sibling.unwrap().unwrap().value().attr("class"));
+        assert_eq!("table", closest.unwrap().unwrap().value().name());
+    }
+
+    #[test]
+    fn test_error_on_invalid_xpath() {
+        let html = Html::parse_fragment("<div></div>");
+
+        let invalid = select(&html.root_element(), "//div[");
+        let not_elements = select(&html.root_element(), "count(//div)");
+
+        matches::assert_matches!(invalid, Err(DebrisError::Selector(_)));
+        matches::assert_matches!(not_elements, Err(DebrisError::Selector(_)));
+    }
+}